@@ -0,0 +1,113 @@
+use alsa::seq::{EvCtrl, Event, EventType};
+use serde_derive::{Deserialize, Serialize};
+
+// Channels are numbered from 1 like on the controllers, programs and controllers from 0
+#[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Eq, Debug)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum MidiTrigger {
+    ProgramChange { channel: u8, program: u8 },
+    ControlChange { channel: u8, controller: u8 },
+}
+
+impl MidiTrigger {
+    pub fn from_event(event: &Event) -> Option<MidiTrigger> {
+        MidiTrigger::from_control(event.get_type(), event.get_data::<EvCtrl>()?)
+    }
+
+    // Foot switches and buttons send a high value when pressed and zero when released,
+    // only the presses trigger
+    fn from_control(event_type: EventType, control: EvCtrl) -> Option<MidiTrigger> {
+        let channel = control.channel.checked_add(1)?;
+
+        match event_type {
+            EventType::Pgmchange => {
+                Some(MidiTrigger::ProgramChange { channel, program: u8::try_from(control.value).ok()? })
+            }
+            EventType::Controller if control.value >= 64 => {
+                Some(MidiTrigger::ControlChange { channel, controller: u8::try_from(control.param).ok()? })
+            }
+            _ => None,
+        }
+    }
+
+    pub fn caption(&self) -> String {
+        match self {
+            MidiTrigger::ProgramChange { channel, program } => {
+                format!("Program Change {} on channel {}", program, channel)
+            }
+            MidiTrigger::ControlChange { channel, controller } => format!("CC {} on channel {}", controller, channel),
+        }
+    }
+}
+
+// Ports are identified by their `client name:port name` keys, like in the snapshots
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum BindingAction {
+    LoadSnapshot { name: String },
+    ToggleConnection { sender: String, dest: String },
+    NextSnapshot,
+    PreviousSnapshot,
+}
+
+impl BindingAction {
+    pub fn caption(&self) -> String {
+        match self {
+            BindingAction::LoadSnapshot { name } => format!("load snapshot {}", name),
+            BindingAction::ToggleConnection { sender, dest } => format!("toggle {} -> {}", sender, dest),
+            BindingAction::NextSnapshot => "next snapshot".to_owned(),
+            BindingAction::PreviousSnapshot => "previous snapshot".to_owned(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub struct MidiBinding {
+    pub port: String,
+    pub trigger: MidiTrigger,
+    pub action: BindingAction,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn control(channel: u8, param: u32, value: i32) -> EvCtrl {
+        EvCtrl { channel, param, value }
+    }
+
+    #[test]
+    fn triggers_are_read_from_events() {
+        assert_eq!(
+            MidiTrigger::from_control(EventType::Pgmchange, control(0, 0, 5)),
+            Some(MidiTrigger::ProgramChange { channel: 1, program: 5 })
+        );
+        assert_eq!(
+            MidiTrigger::from_control(EventType::Controller, control(9, 80, 127)),
+            Some(MidiTrigger::ControlChange { channel: 10, controller: 80 })
+        );
+        assert_eq!(MidiTrigger::from_control(EventType::Controller, control(9, 80, 0)), None);
+        assert_eq!(MidiTrigger::from_control(EventType::Noteon, control(0, 60, 100)), None);
+    }
+
+    #[test]
+    fn bindings_are_stored_as_tables() {
+        let binding = MidiBinding {
+            port: "FCB1010:FCB1010 MIDI 1".to_owned(),
+            trigger: MidiTrigger::ProgramChange { channel: 1, program: 3 },
+            action: BindingAction::ToggleConnection { sender: "a:b".to_owned(), dest: "c:d".to_owned() },
+        };
+
+        let binding_toml = toml::to_string(&binding).unwrap();
+        assert!(binding_toml.contains("type = \"program-change\""));
+        assert_eq!(toml::from_str::<MidiBinding>(&binding_toml).unwrap(), binding);
+
+        let binding = toml::from_str::<MidiBinding>(
+            "port = \"x:y\"\ntrigger = { type = \"control-change\", channel = 2, controller = 64 }\naction = { type = \"next-snapshot\" }\n",
+        )
+        .unwrap();
+        assert_eq!(binding.trigger, MidiTrigger::ControlChange { channel: 2, controller: 64 });
+        assert_eq!(binding.action, BindingAction::NextSnapshot);
+    }
+}
@@ -0,0 +1,161 @@
+use std::path::{Path, PathBuf};
+
+use sdl2::rect::Rect;
+use sdl2::render::{Canvas, RenderTarget};
+
+use crate::error::Error;
+use crate::graphics::{draw_frame, draw_string, draw_tiled_background, PixelDimension, PixelPosition};
+use crate::theme::{Theme, ThemeEntry};
+
+// The previewed theme is only stored in the config once it's applied
+pub struct ThemeBrowser {
+    entries: Vec<ThemeEntry>,
+    selected: usize,
+    original_manifest_path: PathBuf,
+    previewing: bool,
+}
+
+impl ThemeBrowser {
+    pub fn new(current_manifest_path: &Path) -> Result<Option<ThemeBrowser>, Error> {
+        let entries = Theme::available_themes()?;
+        if entries.is_empty() {
+            return Ok(None);
+        }
+
+        let selected = entries.iter().position(|entry| entry.manifest_path == current_manifest_path).unwrap_or(0);
+
+        Ok(Some(ThemeBrowser {
+            entries,
+            selected,
+            original_manifest_path: current_manifest_path.to_owned(),
+            previewing: false,
+        }))
+    }
+
+    // The current theme stays until another entry is selected
+    pub fn previewed_manifest_path(&self) -> &Path {
+        if self.previewing {
+            &self.entries[self.selected].manifest_path
+        } else {
+            &self.original_manifest_path
+        }
+    }
+
+    pub fn select(&mut self, index: usize) -> bool {
+        let last_selected = self.selected;
+        self.selected = index.min(self.entries.len() - 1);
+
+        let changed = self.selected != last_selected;
+        self.previewing |= changed;
+        changed
+    }
+
+    pub fn select_previous(&mut self) -> bool {
+        self.select(self.selected.saturating_sub(1))
+    }
+
+    pub fn select_next(&mut self) -> bool {
+        self.select(self.selected + 1)
+    }
+
+    fn lines(&self) -> Vec<String> {
+        let mut lines = self
+            .entries
+            .iter()
+            .enumerate()
+            .map(|(index, entry)| {
+                let marker = if index == self.selected { '>' } else { ' ' };
+                format!("{} {}", marker, entry.metadata.name)
+            })
+            .collect::<Vec<String>>();
+
+        let entry = &self.entries[self.selected];
+        lines.push(String::new());
+        lines.push(format!("Name:     {}", entry.metadata.name));
+        lines.push(format!("Version:  {}", entry.metadata.version));
+
+        if let Some(authors) = &entry.metadata.authors {
+            for (author_index, author) in authors.iter().enumerate() {
+                let caption = if author_index == 0 { "Authors:" } else { "" };
+                lines.push(format!("{:<9} {}", caption, author));
+            }
+        }
+
+        if let Some(license) = &entry.metadata.license {
+            lines.push(format!("License:  {}", license));
+        }
+
+        lines.push(format!("Location: {}", entry.location));
+
+        if let Some(comment) = &entry.metadata.comment {
+            lines.push(String::new());
+            lines.extend(comment.trim().lines().map(|line| line.trim().to_owned()));
+        }
+
+        lines.push(String::new());
+        lines.push("Up/Down: Preview  Enter: Apply  Escape: Cancel".to_owned());
+        lines
+    }
+
+    pub fn dimension(&self, theme: &Theme) -> PixelDimension {
+        let lines = self.lines();
+        let padding = theme.borders_texture.tile_size;
+
+        PixelDimension {
+            width: lines.iter().map(|line| line.chars().count()).max().unwrap_or(0)
+                * theme.font_texture.tile_size.width
+                + 2 * padding.width,
+            height: lines.len() * theme.font_texture.tile_size.height + 2 * padding.height,
+        }
+    }
+
+    pub fn entry_under_position(&self, theme: &Theme, target: PixelPosition, position: PixelPosition) -> Option<usize> {
+        let (px, py) = (
+            position.x - target.x - theme.borders_texture.tile_size.width as isize,
+            position.y - target.y - theme.borders_texture.tile_size.height as isize,
+        );
+
+        if (px < 0) || (py < 0) || (px as usize >= self.dimension(theme).width) {
+            return None;
+        }
+
+        let entry_index = py as usize / theme.font_texture.tile_size.height;
+        if entry_index < self.entries.len() {
+            Some(entry_index)
+        } else {
+            None
+        }
+    }
+
+    pub fn render<T: RenderTarget>(
+        &self,
+        canvas: &mut Canvas<T>,
+        theme: &Theme,
+        target: PixelPosition,
+    ) -> Result<(), Error> {
+        let dimension = self.dimension(theme);
+
+        canvas.set_clip_rect(Some(Rect::new(
+            target.x as i32,
+            target.y as i32,
+            dimension.width as u32,
+            dimension.height as u32,
+        )));
+        draw_tiled_background(canvas, &theme.background_texture)?;
+        canvas.set_clip_rect(None);
+
+        draw_frame(canvas, &theme.borders_texture, target, dimension)?;
+
+        for (line_index, line) in self.lines().iter().enumerate() {
+            let text_position = PixelPosition {
+                x: target.x + theme.borders_texture.tile_size.width as isize,
+                y: target.y
+                    + theme.borders_texture.tile_size.height as isize
+                    + line_index as isize * theme.font_texture.tile_size.height as isize,
+            };
+            draw_string(canvas, &theme.font_texture, line, text_position, 0)?;
+        }
+
+        Ok(())
+    }
+}
@@ -1,14 +1,178 @@
 use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::PathBuf;
 
+use crate::bindings::MidiBinding;
 use crate::error::Error;
 
-#[derive(Serialize, Deserialize, Default)]
+#[derive(Serialize, Deserialize, Default, Copy, Clone, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum SortMode {
+    #[default]
+    ClientId,
+    Name,
+    UserDefined,
+    ConnectedFirst,
+}
+
+impl SortMode {
+    pub const ALL: [SortMode; 4] =
+        [SortMode::ClientId, SortMode::Name, SortMode::UserDefined, SortMode::ConnectedFirst];
+
+    pub fn caption(&self) -> &'static str {
+        match self {
+            SortMode::ClientId => "Client id",
+            SortMode::Name => "Name",
+            SortMode::UserDefined => "User-defined order",
+            SortMode::ConnectedFirst => "Connected first",
+        }
+    }
+}
+
+// Levels are ordered by verbosity, every level includes the ones before it
+#[derive(Serialize, Deserialize, Default, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "kebab-case")]
+pub enum LogLevel {
+    Off,
+    Error,
+    #[default]
+    Info,
+    Debug,
+}
+
+impl LogLevel {
+    pub fn caption(&self) -> &'static str {
+        match self {
+            LogLevel::Off => "OFF",
+            LogLevel::Error => "ERROR",
+            LogLevel::Info => "INFO",
+            LogLevel::Debug => "DEBUG",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct LoggingConfig {
+    pub level: LogLevel,
+    // Errors are printed to stderr regardless of this
+    pub stderr: bool,
+    // In bytes, the log file is rotated when it would grow larger than this
+    pub max_file_size: u64,
+    pub rotated_files: usize,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> LoggingConfig {
+        LoggingConfig { level: LogLevel::default(), stderr: false, max_file_size: 1024 * 1024, rotated_files: 3 }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct ControlConfig {
+    pub socket: bool,
+    // Defaults to `$XDG_RUNTIME_DIR/aseqmatrix.sock`
+    pub socket_path: Option<PathBuf>,
+    pub osc: bool,
+    // Only local clients can reach the default address
+    pub osc_address: String,
+}
+
+impl Default for ControlConfig {
+    fn default() -> ControlConfig {
+        ControlConfig { socket: true, socket_path: None, osc: false, osc_address: "127.0.0.1:9000".to_owned() }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct SnapshotConfig {
+    // All Notes Off and sustain off are sent to the destinations losing a sender
+    pub release_notes: bool,
+}
+
+impl Default for SnapshotConfig {
+    fn default() -> SnapshotConfig {
+        SnapshotConfig { release_notes: true }
+    }
+}
+
+// Client filters only affect the rows and columns, the connections of filtered clients
+// still take part in the feedback loop checks.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct ClientFilters {
+    pub exclude_kernel_clients: bool,
+    pub exclude_own_client: bool,
+    // Client names or numbers, an empty include list includes every client
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+}
+
+impl Default for ClientFilters {
+    fn default() -> ClientFilters {
+        ClientFilters {
+            exclude_kernel_clients: false,
+            exclude_own_client: true,
+            include: Vec::new(),
+            exclude: vec!["System".to_owned(), "Midi Through".to_owned()],
+        }
+    }
+}
+
+impl ClientFilters {
+    // Clients below this number are kernel clients, including the hardware MIDI devices
+    const FIRST_USER_CLIENT: i32 = 128;
+
+    pub fn accepts(&self, client_number: i32, client_name: &str, own_client: Option<i32>) -> bool {
+        let listed = |list: &[String]| {
+            list.iter().any(|entry| (entry == client_name) || (entry.parse::<i32>().ok() == Some(client_number)))
+        };
+
+        let kernel_client = client_number < Self::FIRST_USER_CLIENT;
+        let own = Some(client_number) == own_client;
+
+        if (self.exclude_kernel_clients && kernel_client) || (self.exclude_own_client && own) {
+            return false;
+        }
+
+        (self.include.is_empty() || listed(&self.include)) && !listed(&self.exclude)
+    }
+}
+
+#[derive(Serialize, Deserialize, Default, Clone)]
 #[serde(rename_all = "kebab-case")]
 pub struct AppConfig {
     pub show_addresses: bool,
+    #[serde(default)]
+    pub show_crosshair: bool,
     pub theme_manifest_path: PathBuf,
+    // Ports are identified by their `client name:port name` keys
+    #[serde(default)]
+    pub hidden_ports: Vec<String>,
+    #[serde(default)]
+    pub sort_mode: SortMode,
+    #[serde(default)]
+    pub pinned_ports: Vec<String>,
+    // The rows and the columns are ordered separately, a port can be on both
+    #[serde(default)]
+    pub input_port_order: Vec<String>,
+    #[serde(default)]
+    pub output_port_order: Vec<String>,
+    #[serde(default)]
+    pub aliases: BTreeMap<String, String>,
+    #[serde(default)]
+    pub client_filters: ClientFilters,
+    #[serde(default)]
+    pub logging: LoggingConfig,
+    #[serde(default)]
+    pub control: ControlConfig,
+    #[serde(default)]
+    pub snapshots: SnapshotConfig,
+    #[serde(default)]
+    pub midi_bindings: Vec<MidiBinding>,
 }
 
 impl AppConfig {
@@ -33,7 +197,22 @@ impl AppConfig {
 
             Ok(app_config)
         } else {
-            Ok(AppConfig { show_addresses: false, theme_manifest_path: Self::default_theme_manifest_path() })
+            Ok(AppConfig {
+                show_addresses: false,
+                show_crosshair: false,
+                theme_manifest_path: Self::default_theme_manifest_path(),
+                hidden_ports: Vec::new(),
+                sort_mode: SortMode::default(),
+                pinned_ports: Vec::new(),
+                input_port_order: Vec::new(),
+                output_port_order: Vec::new(),
+                aliases: BTreeMap::new(),
+                client_filters: ClientFilters::default(),
+                logging: LoggingConfig::default(),
+                control: ControlConfig::default(),
+                snapshots: SnapshotConfig::default(),
+                midi_bindings: Vec::new(),
+            })
         }
     }
 
@@ -50,3 +229,71 @@ impl AppConfig {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn client_filters(include: &[&str], exclude: &[&str]) -> ClientFilters {
+        ClientFilters {
+            include: include.iter().map(|entry| entry.to_string()).collect(),
+            exclude: exclude.iter().map(|entry| entry.to_string()).collect(),
+            ..ClientFilters::default()
+        }
+    }
+
+    #[test]
+    fn system_clients_are_excluded_by_default() {
+        let client_filters = ClientFilters::default();
+
+        assert!(!client_filters.accepts(0, "System", None));
+        assert!(!client_filters.accepts(14, "Midi Through", None));
+        assert!(client_filters.accepts(20, "nanoKEY2", None));
+        assert!(client_filters.accepts(128, "Surge XT", None));
+    }
+
+    #[test]
+    fn clients_are_listed_by_name_or_number() {
+        let client_filters = client_filters(&["nanoKEY2", "130"], &[]);
+
+        assert!(client_filters.accepts(20, "nanoKEY2", None));
+        assert!(client_filters.accepts(130, "FluidSynth", None));
+        assert!(!client_filters.accepts(128, "Surge XT", None));
+        // Names have to match exactly
+        assert!(!client_filters.accepts(21, "nanoKEY", None));
+    }
+
+    #[test]
+    fn empty_include_list_includes_every_client() {
+        let client_filters = client_filters(&[], &[]);
+
+        assert!(client_filters.accepts(0, "System", None));
+        assert!(client_filters.accepts(128, "Surge XT", None));
+    }
+
+    #[test]
+    fn exclude_list_overrides_include_list() {
+        let client_filters = client_filters(&["nanoKEY2", "Surge XT"], &["128"]);
+
+        assert!(client_filters.accepts(20, "nanoKEY2", None));
+        assert!(!client_filters.accepts(128, "Surge XT", None));
+    }
+
+    #[test]
+    fn kernel_and_own_clients_are_excluded_by_their_flags() {
+        let mut client_filters = client_filters(&[], &[]);
+
+        assert!(!client_filters.accepts(129, "aseqmatrix", Some(129)));
+        client_filters.exclude_own_client = false;
+        assert!(client_filters.accepts(129, "aseqmatrix", Some(129)));
+
+        assert!(client_filters.accepts(20, "nanoKEY2", None));
+        client_filters.exclude_kernel_clients = true;
+        assert!(!client_filters.accepts(20, "nanoKEY2", None));
+        assert!(client_filters.accepts(128, "Surge XT", None));
+
+        // The flags win over the include list
+        client_filters.include = vec!["nanoKEY2".to_owned()];
+        assert!(!client_filters.accepts(20, "nanoKEY2", None));
+    }
+}
@@ -0,0 +1,338 @@
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use alsa::seq::Addr;
+use serde::Deserialize;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::config::LogLevel;
+use crate::error::Error;
+use crate::logging;
+use crate::sequencer::{CommandOutcome, SequencerCommand};
+use crate::snapshot::Snapshot;
+use crate::AppState;
+
+// Slow subscribers are dropped instead of holding up the notifications of the others
+const NOTIFICATION_WRITE_TIMEOUT: Duration = Duration::from_millis(100);
+
+// The runtime directory is private to the user, the shared temporary directory is not used
+// as a fallback
+pub fn default_socket_path() -> Result<PathBuf, Error> {
+    dirs::runtime_dir()
+        .map(|runtime_dir| runtime_dir.join("aseqmatrix.sock"))
+        .ok_or(Error::GeneralError("XDG_RUNTIME_DIR is not set, configure the socket-path"))
+}
+
+// Ports are given either as `client:port` addresses or as `client name:port name` keys
+#[derive(Deserialize, PartialEq, Eq, Debug)]
+#[serde(tag = "command", rename_all = "kebab-case")]
+enum ControlRequest {
+    ListPorts,
+    ListConnections,
+    Connect { sender: String, dest: String },
+    Disconnect { sender: String, dest: String },
+    ListSnapshots,
+    SaveSnapshot { name: String },
+    LoadSnapshot { name: String },
+    Subscribe,
+}
+
+#[derive(Serialize)]
+struct PortDescription {
+    address: String,
+    client: String,
+    name: String,
+    key: String,
+    readable: bool,
+    writable: bool,
+}
+
+#[derive(Serialize)]
+struct ConnectionDescription {
+    sender: String,
+    dest: String,
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+enum ControlResult {
+    Ports(Vec<PortDescription>),
+    Connections(Vec<ConnectionDescription>),
+    Snapshots(Vec<String>),
+    Done,
+}
+
+// The `id` of the request is echoed back as it was given, whatever its type
+#[derive(Serialize)]
+#[serde(untagged)]
+enum ControlResponse {
+    Success { id: Value, ok: bool, result: ControlResult },
+    Failure { id: Value, ok: bool, error: String },
+}
+
+#[derive(Serialize)]
+struct ChangeNotification {
+    event: &'static str,
+    ports: Vec<PortDescription>,
+    connections: Vec<ConnectionDescription>,
+}
+
+fn address_string(port_addr: Addr) -> String {
+    format!("{}:{}", port_addr.client, port_addr.port)
+}
+
+fn port_descriptions(app: &AppState) -> Vec<PortDescription> {
+    app.ports
+        .iter()
+        .map(|port| PortDescription {
+            address: address_string(port.addr),
+            client: port.client_name.clone(),
+            name: port.name.clone(),
+            key: port.key(),
+            readable: port.readable(),
+            writable: port.writable(),
+        })
+        .collect()
+}
+
+fn connection_descriptions(app: &AppState) -> Vec<ConnectionDescription> {
+    app.connections
+        .iter()
+        .map(|&(sender, dest)| ConnectionDescription { sender: address_string(sender), dest: address_string(dest) })
+        .collect()
+}
+
+fn find_port(app: &AppState, port: &str) -> Result<Addr, Error> {
+    app.find_port(port).ok_or(Error::GeneralError("unknown port"))
+}
+
+// Responses and notifications are written from different threads, the lock keeps their
+// lines from interleaving
+type ClientWriter = Arc<Mutex<UnixStream>>;
+
+// The state is shared with the UI, the lock is only held while handling a single request.
+// Notifications are written by a separate thread, the UI only builds them.
+pub struct ControlServer {
+    socket_path: PathBuf,
+    notification_sender: Sender<String>,
+}
+
+impl ControlServer {
+    pub fn new(app: Arc<Mutex<AppState>>, socket_path: &Path) -> Result<ControlServer, Error> {
+        // A socket file left behind by a crashed instance is replaced, a live one is not
+        if UnixStream::connect(socket_path).is_ok() {
+            return Err(Error::GeneralError("control socket is already in use"));
+        }
+        let _ = fs::remove_file(socket_path);
+
+        // Only our user may connect. The umask is process-wide, the other threads would create
+        // their files with it too, so the permissions are set after binding instead.
+        let listener = UnixListener::bind(socket_path)?;
+        fs::set_permissions(socket_path, fs::Permissions::from_mode(0o600))?;
+        let subscribers = Arc::new(Mutex::new(Vec::new()));
+
+        {
+            let subscribers = Arc::clone(&subscribers);
+            thread::spawn(move || {
+                for stream in listener.incoming().flatten() {
+                    let app = Arc::clone(&app);
+                    let subscribers = Arc::clone(&subscribers);
+                    thread::spawn(move || handle_client(&app, stream, &subscribers));
+                }
+            });
+        }
+
+        let (notification_sender, notification_receiver) = mpsc::channel();
+        thread::spawn(move || send_notifications(notification_receiver, &subscribers));
+
+        logging::info(&format!("Control socket listening on {}", socket_path.display()));
+        Ok(ControlServer { socket_path: socket_path.to_owned(), notification_sender })
+    }
+
+    pub fn notify_change(&self, app: &AppState) {
+        let notification = ChangeNotification {
+            event: "changed",
+            ports: port_descriptions(app),
+            connections: connection_descriptions(app),
+        };
+        let line = match serde_json::to_string(&notification) {
+            Ok(notification) => format!("{}\n", notification),
+            Err(_) => return,
+        };
+
+        let _ = self.notification_sender.send(line);
+    }
+}
+
+// Every notification describes the whole state, only the latest one of a burst is written
+fn send_notifications(notification_receiver: Receiver<String>, subscribers: &Mutex<Vec<ClientWriter>>) {
+    while let Ok(line) = notification_receiver.recv() {
+        let line = notification_receiver.try_iter().last().unwrap_or(line);
+        subscribers.lock().unwrap().retain(|writer| writer.lock().unwrap().write_all(line.as_bytes()).is_ok());
+    }
+}
+
+impl Drop for ControlServer {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.socket_path);
+    }
+}
+
+fn handle_client(app: &Mutex<AppState>, stream: UnixStream, subscribers: &Mutex<Vec<ClientWriter>>) {
+    let writer = match stream.try_clone() {
+        Ok(writer) => Arc::new(Mutex::new(writer)),
+        Err(_) => return,
+    };
+
+    for line in BufReader::new(stream).lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        if logging::enabled(LogLevel::Debug) {
+            logging::debug(&format!("Control request: {}", line));
+        }
+
+        // The `id` is answered even when the rest of the request is invalid
+        let request = serde_json::from_str::<Value>(&line);
+        let id = request.as_ref().ok().and_then(|request| request.get("id")).cloned().unwrap_or(Value::Null);
+
+        let result = request
+            .and_then(ControlRequest::deserialize)
+            .map_err(Error::from)
+            .and_then(|request| handle_request(app, request, &writer, subscribers));
+        let response = match result {
+            Ok(result) => ControlResponse::Success { id, ok: true, result },
+            Err(err) => ControlResponse::Failure { id, ok: false, error: err.to_string() },
+        };
+        let response = match serde_json::to_string(&response) {
+            Ok(response) => response,
+            Err(_) => break,
+        };
+
+        if writer.lock().unwrap().write_all(format!("{}\n", response).as_bytes()).is_err() {
+            break;
+        }
+    }
+}
+
+fn wait_for_outcome(outcome_receiver: Receiver<CommandOutcome>) -> Result<(), Error> {
+    outcome_receiver.recv().map_err(|_| Error::GeneralError("sequencer thread is not running"))?
+}
+
+// Routing changes are carried out by the sequencer thread, they're answered once it's done.
+// The subscribers are notified about the changes too.
+fn handle_request(
+    app: &Mutex<AppState>,
+    request: ControlRequest,
+    writer: &ClientWriter,
+    subscribers: &Mutex<Vec<ClientWriter>>,
+) -> Result<ControlResult, Error> {
+    match request {
+        ControlRequest::ListPorts => Ok(ControlResult::Ports(port_descriptions(&app.lock().unwrap()))),
+        ControlRequest::ListConnections => {
+            Ok(ControlResult::Connections(connection_descriptions(&app.lock().unwrap())))
+        }
+        ControlRequest::Connect { sender, dest } => {
+            let outcome_receiver = {
+                let app = app.lock().unwrap();
+                let connection = (find_port(&app, &sender)?, find_port(&app, &dest)?);
+                app.send_command_with_outcome(SequencerCommand::Connect(vec![connection]))?
+            };
+
+            wait_for_outcome(outcome_receiver)?;
+            Ok(ControlResult::Done)
+        }
+        ControlRequest::Disconnect { sender, dest } => {
+            let outcome_receiver = {
+                let app = app.lock().unwrap();
+                let connection = (find_port(&app, &sender)?, find_port(&app, &dest)?);
+                app.send_command_with_outcome(SequencerCommand::Disconnect(vec![connection]))?
+            };
+
+            wait_for_outcome(outcome_receiver)?;
+            Ok(ControlResult::Done)
+        }
+        ControlRequest::ListSnapshots => Ok(ControlResult::Snapshots(Snapshot::names()?)),
+        ControlRequest::SaveSnapshot { name } => {
+            app.lock().unwrap().save_snapshot(&name)?;
+            Ok(ControlResult::Done)
+        }
+        ControlRequest::LoadSnapshot { name } => {
+            let command = SequencerCommand::LoadSnapshot(name.clone(), Snapshot::load(&name)?);
+            let outcome_receiver = app.lock().unwrap().send_command_with_outcome(command)?;
+
+            wait_for_outcome(outcome_receiver)?;
+            Ok(ControlResult::Done)
+        }
+        ControlRequest::Subscribe => {
+            writer.lock().unwrap().set_write_timeout(Some(NOTIFICATION_WRITE_TIMEOUT))?;
+            subscribers.lock().unwrap().push(Arc::clone(writer));
+            Ok(ControlResult::Done)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn socket_is_private_to_the_user() {
+        let socket_path = std::env::temp_dir().join(format!("aseqmatrix-test-{}.sock", std::process::id()));
+        let app = Arc::new(Mutex::new(AppState::with_config(Default::default())));
+        let control_server = ControlServer::new(app, &socket_path).unwrap();
+
+        assert_eq!(fs::metadata(&socket_path).unwrap().permissions().mode() & 0o777, 0o600);
+
+        drop(control_server);
+        assert!(!socket_path.exists());
+    }
+
+    #[test]
+    fn requests_are_parsed() {
+        let request = serde_json::from_str::<ControlRequest>(
+            r#"{"id": 1, "command": "connect", "sender": "nanoKEY2:nanoKEY2 MIDI 1", "dest": "130:0"}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            request,
+            ControlRequest::Connect { sender: "nanoKEY2:nanoKEY2 MIDI 1".to_owned(), dest: "130:0".to_owned() }
+        );
+
+        assert_eq!(
+            serde_json::from_str::<ControlRequest>(r#"{"command": "list-ports"}"#).unwrap(),
+            ControlRequest::ListPorts
+        );
+        assert!(serde_json::from_str::<ControlRequest>(r#"{"command": "connect", "sender": "130:0"}"#).is_err());
+        assert!(serde_json::from_str::<ControlRequest>(r#"{"command": "reboot"}"#).is_err());
+    }
+
+    #[test]
+    fn responses_are_written_on_one_line() {
+        let response = ControlResponse::Success { id: Value::from(1), ok: true, result: ControlResult::Done };
+        assert_eq!(serde_json::to_string(&response).unwrap(), r#"{"id":1,"ok":true,"result":null}"#);
+
+        let response = ControlResponse::Success {
+            id: Value::from("a"),
+            ok: true,
+            result: ControlResult::Snapshots(vec!["Verse".to_owned()]),
+        };
+        assert_eq!(serde_json::to_string(&response).unwrap(), r#"{"id":"a","ok":true,"result":["Verse"]}"#);
+
+        let response = ControlResponse::Failure { id: Value::Null, ok: false, error: "unknown port\n".to_owned() };
+        assert_eq!(serde_json::to_string(&response).unwrap(), r#"{"id":null,"ok":false,"error":"unknown port\n"}"#);
+    }
+}
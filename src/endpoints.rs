@@ -0,0 +1,93 @@
+use alsa::seq::{Addr, ClientIter, PortCap, PortInfo, PortIter, PortSubscribeIter, QuerySubsType, Seq};
+
+use crate::error::Error;
+
+// Port addresses change between sessions, the names are used for identifying them in the config
+pub fn port_key(client_name: &str, port_name: &str) -> String {
+    format!("{}:{}", client_name, port_name)
+}
+
+// Renames are not announced by the sequencer, they are detected by comparing the names
+// periodically. This is a lot cheaper than taking a full snapshot.
+pub fn query_port_keys(seq: &Seq) -> Result<Vec<(Addr, String)>, Error> {
+    let mut port_keys = Vec::new();
+
+    for client in ClientIter::new(seq) {
+        for port in PortIter::new(seq, client.get_client()) {
+            port_keys.push((port.addr(), port_key(client.get_name()?, port.get_name()?)));
+        }
+    }
+
+    port_keys.sort();
+    Ok(port_keys)
+}
+
+#[derive(Clone)]
+pub struct PortEntry {
+    pub addr: Addr,
+    pub client_name: String,
+    pub name: String,
+    pub capability: PortCap,
+}
+
+impl PortEntry {
+    fn new(client_name: &str, port: &PortInfo) -> Result<PortEntry, Error> {
+        Ok(PortEntry {
+            addr: port.addr(),
+            client_name: client_name.to_owned(),
+            name: port.get_name()?.to_owned(),
+            capability: port.get_capability(),
+        })
+    }
+
+    pub fn query(seq: &Seq, addr: Addr) -> Result<PortEntry, Error> {
+        let client = seq.get_any_client_info(addr.client)?;
+        let port = seq.get_any_port_info(addr)?;
+        PortEntry::new(client.get_name()?, &port)
+    }
+
+    pub fn key(&self) -> String {
+        port_key(&self.client_name, &self.name)
+    }
+
+    pub fn readable(&self) -> bool {
+        self.capability.contains(PortCap::SUBS_READ)
+    }
+
+    pub fn writable(&self) -> bool {
+        self.capability.contains(PortCap::SUBS_WRITE)
+    }
+}
+
+// Every port and connection of the sequencer, unfiltered. Querying it doesn't need
+// the application state, so it's done without holding its lock.
+pub struct EndpointSnapshot {
+    pub ports: Vec<PortEntry>,
+    pub connections: Vec<(Addr, Addr)>,
+}
+
+impl EndpointSnapshot {
+    pub fn query(seq: &Seq) -> Result<EndpointSnapshot, Error> {
+        let mut ports = Vec::new();
+        let mut connections = Vec::new();
+
+        for client in ClientIter::new(seq) {
+            for port in PortIter::new(seq, client.get_client()) {
+                ports.push(PortEntry::new(client.get_name()?, &port)?);
+
+                for sub in PortSubscribeIter::new(seq, port.addr(), QuerySubsType::WRITE) {
+                    connections.push((sub.get_sender(), sub.get_dest()));
+                }
+            }
+        }
+
+        Ok(EndpointSnapshot { ports, connections })
+    }
+}
+
+pub enum EndpointDelta {
+    PortStart(PortEntry),
+    PortExit(Addr),
+    Subscribed(Addr, Addr),
+    Unsubscribed(Addr, Addr),
+}
@@ -9,7 +9,10 @@ use sdl2::IntegerOrSdlError;
 pub enum Error {
     GeneralError(&'static str),
     AlsaError(alsa::Error),
+    CommandError(String),
     IoError(io::Error),
+    JsonError(serde_json::Error),
+    NixError(nix::Error),
     NulError(NulError),
     SdlIntError(IntegerOrSdlError),
     SdlStrError(String),
@@ -23,6 +26,8 @@ impl error::Error for Error {
         match *self {
             Self::AlsaError(ref err) => Some(err),
             Self::IoError(ref err) => Some(err),
+            Self::JsonError(ref err) => Some(err),
+            Self::NixError(ref err) => Some(err),
             Self::NulError(ref err) => Some(err),
             Self::SdlIntError(ref err) => Some(err),
             Self::SdlWindowBuildError(ref err) => Some(err),
@@ -42,9 +47,18 @@ impl fmt::Display for Error {
             Self::AlsaError(ref err) => {
                 write!(f, "ALSA error: {}", err)
             }
+            Self::CommandError(ref err) => {
+                write!(f, "{}", err)
+            }
             Self::IoError(ref err) => {
                 write!(f, "I/O error: {}", err)
             }
+            Self::JsonError(ref err) => {
+                write!(f, "JSON error: {}", err)
+            }
+            Self::NixError(ref err) => {
+                write!(f, "System error: {}", err)
+            }
             Self::NulError(ref err) => {
                 write!(f, "Nul byte error: {}", err)
             }
@@ -79,6 +93,18 @@ impl From<io::Error> for Error {
     }
 }
 
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Self {
+        Self::JsonError(err)
+    }
+}
+
+impl From<nix::Error> for Error {
+    fn from(err: nix::Error) -> Self {
+        Self::NixError(err)
+    }
+}
+
 impl From<NulError> for Error {
     fn from(err: NulError) -> Self {
         Self::NulError(err)
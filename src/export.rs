@@ -0,0 +1,161 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use sdl2::image::SaveSurface;
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::surface::Surface;
+
+use crate::error::{sdl_error, Error};
+use crate::layout::LayoutMetrics;
+use crate::theme::Theme;
+use crate::AppState;
+
+pub fn default_export_path(extension: &str) -> Result<PathBuf, Error> {
+    let export_directory = dirs::picture_dir()
+        .or_else(dirs::home_dir)
+        .ok_or(Error::GeneralError("failed to retrieve pictures directory"))?;
+
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|duration| duration.as_secs()).unwrap_or(0);
+
+    Ok(export_directory.join(format!("aseqmatrix-{}.{}", timestamp, extension)))
+}
+
+pub fn render_offscreen(app: &mut AppState, manifest_path: &Path) -> Result<Surface<'static>, Error> {
+    // The canvas size depends on the theme metrics, which are only known after loading it once
+    let window_dimension = {
+        let probe_canvas =
+            Surface::new(1, 1, PixelFormatEnum::RGBA32).map_err(sdl_error)?.into_canvas().map_err(sdl_error)?;
+        let probe_texture_creator = probe_canvas.texture_creator();
+        let theme = Theme::new(&probe_texture_creator, manifest_path)?;
+        app.update_layout(LayoutMetrics::from_theme(&theme));
+        app.window_dimension(&theme)
+    };
+
+    let mut canvas =
+        Surface::new(window_dimension.width as u32, window_dimension.height as u32, PixelFormatEnum::RGBA32)
+            .map_err(sdl_error)?
+            .into_canvas()
+            .map_err(sdl_error)?;
+    let texture_creator = canvas.texture_creator();
+    let mut theme = Theme::new(&texture_creator, manifest_path)?;
+
+    app.render(&mut canvas, &mut theme)?;
+    Ok(canvas.into_surface())
+}
+
+pub fn export_png(app: &AppState, path: &Path) -> Result<(), Error> {
+    let mut app = app.export_state();
+    let manifest_path = app.config.theme_manifest_path.clone();
+    let surface = render_offscreen(&mut app, &manifest_path)?;
+    surface.save(path).map_err(sdl_error)?;
+    Ok(())
+}
+
+fn escape_xml(text: &str) -> String {
+    text.chars()
+        .map(|character| match character {
+            '&' => "&amp;".to_owned(),
+            '<' => "&lt;".to_owned(),
+            '>' => "&gt;".to_owned(),
+            '"' => "&quot;".to_owned(),
+            '\'' => "&apos;".to_owned(),
+            _ => character.to_string(),
+        })
+        .collect()
+}
+
+pub fn export_svg(app: &AppState, path: &Path) -> Result<(), Error> {
+    let mut app = app.export_state();
+
+    // Only the metrics of the theme are used, the rendition itself is themeless
+    let probe_canvas =
+        Surface::new(1, 1, PixelFormatEnum::RGBA32).map_err(sdl_error)?.into_canvas().map_err(sdl_error)?;
+    let probe_texture_creator = probe_canvas.texture_creator();
+    let theme = Theme::new(&probe_texture_creator, &app.config.theme_manifest_path)?;
+    app.update_layout(LayoutMetrics::from_theme(&theme));
+
+    let window_dimension = app.window_dimension(&theme);
+    let layout = &app.layout;
+    let font_size = layout.metrics.glyph.height;
+
+    let mut svg = Vec::new();
+    svg.push(format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{0}" height="{1}" viewBox="0 0 {0} {1}">"#,
+        window_dimension.width, window_dimension.height
+    ));
+    svg.push(r##"<rect width="100%" height="100%" fill="#ffffff"/>"##.to_owned());
+    svg.push(format!(
+        r##"<g font-family="monospace" font-size="{}" fill="#202020" dominant-baseline="hanging">"##,
+        font_size
+    ));
+
+    for (output_index, output_name) in app.output_names().iter().enumerate() {
+        let arrow = layout.row_arrows[output_index];
+        let text_position = layout.row_labels[output_index].position();
+
+        svg.push(format!(
+            r##"<polygon points="{},{} {},{} {},{}" fill="#808080"/>"##,
+            arrow.x,
+            arrow.y + arrow.height as isize / 2,
+            arrow.x + arrow.width as isize,
+            arrow.y,
+            arrow.x + arrow.width as isize,
+            arrow.y + arrow.height as isize,
+        ));
+        svg.push(format!(
+            r#"<text x="{}" y="{}">{}</text>"#,
+            text_position.x,
+            text_position.y,
+            escape_xml(output_name)
+        ));
+    }
+
+    for (input_index, input_name) in app.input_names().iter().enumerate() {
+        let arrow = layout.column_arrows[input_index];
+        let text_position = layout.column_label_origin(input_index);
+
+        svg.push(format!(
+            r##"<polygon points="{},{} {},{} {},{}" fill="#808080"/>"##,
+            arrow.x,
+            arrow.y,
+            arrow.x + arrow.width as isize,
+            arrow.y,
+            arrow.x + arrow.width as isize / 2,
+            arrow.y + arrow.height as isize,
+        ));
+        svg.push(format!(
+            r#"<text transform="translate({} {}) rotate(-90)">{}</text>"#,
+            text_position.x,
+            text_position.y,
+            escape_xml(input_name)
+        ));
+    }
+
+    svg.push("</g>".to_owned());
+
+    for (output_index, (output_addr, _)) in app.outputs.iter().enumerate() {
+        for (input_index, (input_addr, _)) in app.inputs.iter().enumerate() {
+            let fill = match (input_addr == output_addr, app.connections.contains(&(*input_addr, *output_addr))) {
+                (true, _) => "#d0d0d0",
+                (false, false) => "#f4f4f4",
+                (false, true) => "#3daee9",
+            };
+
+            let cell = layout.cell(input_index, output_index);
+            svg.push(format!(
+                r##"<rect x="{}" y="{}" width="{}" height="{}" rx="2" fill="{}" stroke="#808080"/>"##,
+                cell.x as f64 + 0.5,
+                cell.y as f64 + 0.5,
+                cell.width - 1,
+                cell.height - 1,
+                fill
+            ));
+        }
+    }
+
+    svg.push("</svg>\n".to_owned());
+
+    fs::write(path, svg.join("\n"))?;
+    Ok(())
+}
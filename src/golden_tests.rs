@@ -0,0 +1,125 @@
+use std::path::PathBuf;
+use std::{env, fs};
+
+use alsa::seq::Addr;
+use sdl2::image::{LoadSurface, SaveSurface};
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::surface::Surface;
+
+use crate::config::AppConfig;
+use crate::export::render_offscreen;
+use crate::AppState;
+
+// Set this to regenerate the stored images after an intentional rendering change
+const UPDATE_GOLDEN_VARIABLE: &str = "ASEQMATRIX_UPDATE_GOLDEN";
+
+fn project_path() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+}
+
+fn mock_app_state() -> AppState {
+    let mut app = AppState::with_config(AppConfig::default());
+
+    app.inputs = vec![
+        (Addr { client: 20, port: 0 }, "nanoKEY2 MIDI 1".to_owned()),
+        (Addr { client: 24, port: 0 }, "Arturia KeyStep 32".to_owned()),
+        (Addr { client: 128, port: 0 }, "Surge XT Output".to_owned()),
+        (Addr { client: 129, port: 0 }, "Sequencer".to_owned()),
+    ];
+
+    app.outputs = vec![
+        (Addr { client: 14, port: 0 }, "Midi Through Port-0".to_owned()),
+        (Addr { client: 128, port: 1 }, "Surge XT Input".to_owned()),
+        (Addr { client: 129, port: 0 }, "Sequencer".to_owned()),
+        (Addr { client: 130, port: 0 }, "FluidSynth".to_owned()),
+    ];
+
+    app.connections = vec![
+        (Addr { client: 20, port: 0 }, Addr { client: 128, port: 1 }),
+        (Addr { client: 24, port: 0 }, Addr { client: 130, port: 0 }),
+        (Addr { client: 129, port: 0 }, Addr { client: 130, port: 0 }),
+    ];
+
+    app
+}
+
+fn render_theme(theme_name: &str) -> Surface<'static> {
+    let manifest_path = project_path().join("themes").join(theme_name).join("theme.toml");
+    render_offscreen(&mut mock_app_state(), &manifest_path).unwrap()
+}
+
+fn assert_golden(theme_name: &str) {
+    let rendered = render_theme(theme_name);
+    let golden_path = project_path().join("tests").join("golden").join(format!("{}.png", theme_name));
+
+    if env::var_os(UPDATE_GOLDEN_VARIABLE).is_some() {
+        fs::create_dir_all(golden_path.parent().unwrap()).unwrap();
+        rendered.save(&golden_path).unwrap();
+        return;
+    }
+
+    assert!(
+        golden_path.exists(),
+        "{}: {} is missing, run with {}=1 to create it",
+        theme_name,
+        golden_path.display(),
+        UPDATE_GOLDEN_VARIABLE
+    );
+
+    let golden = Surface::from_file(&golden_path).unwrap().convert_format(PixelFormatEnum::RGBA32).unwrap();
+
+    assert_eq!(
+        (rendered.width(), rendered.height()),
+        (golden.width(), golden.height()),
+        "{}: rendered image size differs from {}",
+        theme_name,
+        golden_path.display()
+    );
+
+    let row_length = rendered.width() as usize * PixelFormatEnum::RGBA32.byte_size_per_pixel();
+    let (rendered_pixels, golden_pixels) = (rendered.without_lock().unwrap(), golden.without_lock().unwrap());
+
+    for y in 0..rendered.height() as usize {
+        let rendered_row = &rendered_pixels[y * rendered.pitch() as usize..][..row_length];
+        let golden_row = &golden_pixels[y * golden.pitch() as usize..][..row_length];
+
+        assert!(
+            rendered_row == golden_row,
+            "{}: rendered image differs from {} in row {}, rerun with {}=1 if this is intentional",
+            theme_name,
+            golden_path.display(),
+            y,
+            UPDATE_GOLDEN_VARIABLE
+        );
+    }
+}
+
+#[test]
+fn golden_analogue() {
+    assert_golden("analogue");
+}
+
+#[test]
+fn golden_breeze() {
+    assert_golden("breeze");
+}
+
+#[test]
+fn golden_breeze_dark() {
+    assert_golden("breeze-dark");
+}
+
+#[test]
+fn golden_memphis() {
+    assert_golden("memphis");
+}
+
+#[test]
+fn golden_orbital() {
+    assert_golden("orbital");
+}
+
+#[test]
+fn golden_test() {
+    assert_golden("test");
+}
@@ -1,37 +1,60 @@
 use std::path::Path;
 
 use sdl2::image::LoadTexture;
+use sdl2::pixels::Color;
 use sdl2::rect::{Point, Rect};
-use sdl2::render::{Canvas, Texture, TextureCreator};
-use sdl2::video::{Window, WindowContext};
+use sdl2::render::{BlendMode, Canvas, RenderTarget, Texture, TextureCreator};
+
+use serde_derive::Deserialize;
 
 use crate::error::{sdl_error, Error};
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
 pub struct PixelPosition {
     pub x: isize,
     pub y: isize,
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
 pub struct PixelDimension {
     pub width: usize,
     pub height: usize,
 }
 
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct PixelRect {
+    pub x: isize,
+    pub y: isize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl PixelRect {
+    pub fn position(&self) -> PixelPosition {
+        PixelPosition { x: self.x, y: self.y }
+    }
+
+    pub fn contains(&self, position: PixelPosition) -> bool {
+        (position.x >= self.x)
+            && (position.y >= self.y)
+            && (position.x < self.x + self.width as isize)
+            && (position.y < self.y + self.height as isize)
+    }
+}
+
 #[derive(Copy, Clone, Debug)]
 pub struct TilePosition {
     pub x: usize,
     pub y: usize,
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Deserialize)]
 pub struct TileDimension {
     pub width: usize,
     pub height: usize,
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Deserialize)]
 pub struct TileRect {
     pub x: usize,
     pub y: usize,
@@ -46,8 +69,8 @@ pub struct TileTexture<'a> {
 }
 
 impl<'a> TileTexture<'a> {
-    pub fn new<P: AsRef<Path>>(
-        texture_creator: &'a TextureCreator<WindowContext>,
+    pub fn new<C, P: AsRef<Path>>(
+        texture_creator: &'a TextureCreator<C>,
         texture_path: P,
         tiles_per_dimension: TileDimension,
     ) -> Result<TileTexture<'a>, Error> {
@@ -63,10 +86,14 @@ impl<'a> TileTexture<'a> {
 
         Ok(TileTexture { texture, tile_size, tiles_per_dimension })
     }
+
+    pub fn rect_dimensions(&self, rect: TileRect) -> PixelDimension {
+        PixelDimension { width: rect.width * self.tile_size.width, height: rect.height * self.tile_size.height }
+    }
 }
 
-pub fn draw_tiles(
-    canvas: &mut Canvas<Window>,
+pub fn draw_tiles<T: RenderTarget>(
+    canvas: &mut Canvas<T>,
     tile_texture: &TileTexture,
     source: TileRect,
     target: PixelPosition,
@@ -90,8 +117,8 @@ pub fn draw_tiles(
     Ok(())
 }
 
-pub fn draw_character(
-    canvas: &mut Canvas<Window>,
+pub fn draw_character<T: RenderTarget>(
+    canvas: &mut Canvas<T>,
     tile_texture: &TileTexture,
     character: char,
     target: PixelPosition,
@@ -133,8 +160,8 @@ pub fn draw_character(
     Ok(())
 }
 
-pub fn draw_string(
-    canvas: &mut Canvas<Window>,
+pub fn draw_string<T: RenderTarget>(
+    canvas: &mut Canvas<T>,
     tile_texture: &TileTexture,
     string: &str,
     target: PixelPosition,
@@ -160,7 +187,37 @@ pub fn draw_string(
     Ok(())
 }
 
-pub fn draw_tiled_background(canvas: &mut Canvas<Window>, texture: &Texture) -> Result<(), Error> {
+pub fn draw_colored_string<T: RenderTarget>(
+    canvas: &mut Canvas<T>,
+    tile_texture: &mut TileTexture,
+    string: &str,
+    target: PixelPosition,
+    rotation: usize,
+    color: Color,
+) -> Result<(), Error> {
+    tile_texture.texture.set_color_mod(color.r, color.g, color.b);
+    tile_texture.texture.set_alpha_mod(color.a);
+    let result = draw_string(canvas, tile_texture, string, target, rotation);
+    tile_texture.texture.set_color_mod(255, 255, 255);
+    tile_texture.texture.set_alpha_mod(255);
+    result
+}
+
+pub fn fill_rect<T: RenderTarget>(
+    canvas: &mut Canvas<T>,
+    target: PixelPosition,
+    dimension: PixelDimension,
+    color: Color,
+) -> Result<(), Error> {
+    canvas.set_blend_mode(BlendMode::Blend);
+    canvas.set_draw_color(color);
+    canvas
+        .fill_rect(Rect::new(target.x as i32, target.y as i32, dimension.width as u32, dimension.height as u32))
+        .map_err(sdl_error)?;
+    Ok(())
+}
+
+pub fn draw_tiled_background<T: RenderTarget>(canvas: &mut Canvas<T>, texture: &Texture) -> Result<(), Error> {
     let (canvas_width, canvas_height) = canvas.output_size().map_err(sdl_error)?;
     let (texture_width, texture_height) = {
         let query = texture.query();
@@ -183,16 +240,30 @@ pub fn draw_tiled_background(canvas: &mut Canvas<Window>, texture: &Texture) ->
     Ok(())
 }
 
-pub fn draw_borders(canvas: &mut Canvas<Window>, borders_texture: &TileTexture) -> Result<(), Error> {
+pub fn draw_borders<T: RenderTarget>(canvas: &mut Canvas<T>, borders_texture: &TileTexture) -> Result<(), Error> {
     let (canvas_width, canvas_height) = canvas.output_size().map_err(sdl_error)?;
 
-    let tile_position_top = 0;
-    let tile_position_left = 0;
-    let tile_position_right = canvas_width as isize - borders_texture.tile_size.width as isize;
-    let tile_position_bottom = canvas_height as isize - borders_texture.tile_size.height as isize;
+    draw_frame(
+        canvas,
+        borders_texture,
+        PixelPosition { x: 0, y: 0 },
+        PixelDimension { width: canvas_width as usize, height: canvas_height as usize },
+    )
+}
+
+pub fn draw_frame<T: RenderTarget>(
+    canvas: &mut Canvas<T>,
+    borders_texture: &TileTexture,
+    target: PixelPosition,
+    dimension: PixelDimension,
+) -> Result<(), Error> {
+    let tile_position_top = target.y;
+    let tile_position_left = target.x;
+    let tile_position_right = target.x + dimension.width as isize - borders_texture.tile_size.width as isize;
+    let tile_position_bottom = target.y + dimension.height as isize - borders_texture.tile_size.height as isize;
 
-    for x in 1..canvas_width as usize / borders_texture.tile_size.width {
-        let tile_position_x = x as isize * borders_texture.tile_size.width as isize;
+    for x in 1..dimension.width / borders_texture.tile_size.width {
+        let tile_position_x = target.x + x as isize * borders_texture.tile_size.width as isize;
         draw_tiles(
             canvas,
             borders_texture,
@@ -207,8 +278,8 @@ pub fn draw_borders(canvas: &mut Canvas<Window>, borders_texture: &TileTexture)
         )?;
     }
 
-    for y in 1..canvas_height as usize / borders_texture.tile_size.height {
-        let tile_position_y = y as isize * borders_texture.tile_size.height as isize;
+    for y in 1..dimension.height / borders_texture.tile_size.height {
+        let tile_position_y = target.y + y as isize * borders_texture.tile_size.height as isize;
         draw_tiles(
             canvas,
             borders_texture,
@@ -0,0 +1,283 @@
+use crate::graphics::{PixelDimension, PixelPosition, PixelRect};
+use crate::theme::Theme;
+
+// Everything the layout depends on, extracted from the theme so it can be computed without SDL
+#[derive(Copy, Clone, Debug, Default)]
+pub struct LayoutMetrics {
+    pub window_margin: usize,
+    pub label_spacing: usize,
+    pub arrow_spacing: usize,
+    pub grid_spacing: usize,
+    pub button: PixelDimension,
+    pub arrow_left: PixelDimension,
+    pub arrow_down: PixelDimension,
+    pub glyph: PixelDimension,
+}
+
+impl LayoutMetrics {
+    pub fn from_theme(theme: &Theme) -> LayoutMetrics {
+        LayoutMetrics {
+            window_margin: theme.manifest.metrics.window_margin,
+            label_spacing: theme.manifest.metrics.label_spacing,
+            arrow_spacing: theme.manifest.metrics.arrow_spacing,
+            grid_spacing: theme.manifest.metrics.grid_spacing,
+            button: theme.button_dimensions(),
+            arrow_left: theme.arrow_left_dimensions(),
+            arrow_down: theme.arrow_down_dimensions(),
+            glyph: PixelDimension {
+                width: theme.font_texture.tile_size.width,
+                height: theme.font_texture.tile_size.height,
+            },
+        }
+    }
+
+    fn button_pitch(&self) -> PixelDimension {
+        PixelDimension { width: self.button.width + self.grid_spacing, height: self.button.height + self.grid_spacing }
+    }
+
+    fn text_length(&self, text: &str) -> usize {
+        text.chars().count() * self.glyph.width
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LayoutLabel {
+    Row(usize),
+    Column(usize),
+}
+
+// Rows are the outputs, columns are the inputs. Column labels are drawn rotated,
+// their rects cover the area occupied by the rotated text.
+#[derive(Clone, Debug, Default)]
+pub struct Layout {
+    pub metrics: LayoutMetrics,
+    pub grid: PixelRect,
+    pub row_arrows: Vec<PixelRect>,
+    pub row_labels: Vec<PixelRect>,
+    pub column_arrows: Vec<PixelRect>,
+    pub column_labels: Vec<PixelRect>,
+    pub dimension: PixelDimension,
+}
+
+impl Layout {
+    pub fn new(metrics: LayoutMetrics, row_labels: &[String], column_labels: &[String]) -> Layout {
+        let pitch = metrics.button_pitch();
+        let margin = metrics.window_margin as isize;
+
+        let grid = PixelRect {
+            x: margin,
+            y: margin,
+            width: (column_labels.len() * pitch.width).saturating_sub(metrics.grid_spacing),
+            height: (row_labels.len() * pitch.height).saturating_sub(metrics.grid_spacing),
+        };
+
+        let row_arrows = (0..row_labels.len())
+            .map(|row| PixelRect {
+                x: grid.x + grid.width as isize + metrics.arrow_spacing as isize,
+                y: grid.y + (row * pitch.height) as isize,
+                width: metrics.arrow_left.width,
+                height: metrics.arrow_left.height,
+            })
+            .collect::<Vec<PixelRect>>();
+
+        let row_labels = row_arrows
+            .iter()
+            .zip(row_labels)
+            .map(|(arrow, label)| PixelRect {
+                x: arrow.x + arrow.width as isize + metrics.label_spacing as isize,
+                y: arrow.y + (metrics.button.height as isize - metrics.glyph.height as isize) / 2,
+                width: metrics.text_length(label),
+                height: metrics.glyph.height,
+            })
+            .collect::<Vec<PixelRect>>();
+
+        let column_arrows = (0..column_labels.len())
+            .map(|column| PixelRect {
+                x: grid.x + (column * pitch.width) as isize,
+                y: grid.y + grid.height as isize + metrics.arrow_spacing as isize,
+                width: metrics.arrow_down.width,
+                height: metrics.arrow_down.height,
+            })
+            .collect::<Vec<PixelRect>>();
+
+        let column_labels = column_arrows
+            .iter()
+            .zip(column_labels)
+            .map(|(arrow, label)| PixelRect {
+                x: arrow.x + (metrics.button.width as isize - metrics.glyph.height as isize) / 2,
+                y: arrow.y + arrow.height as isize + metrics.label_spacing as isize,
+                width: metrics.glyph.height,
+                height: metrics.text_length(label),
+            })
+            .collect::<Vec<PixelRect>>();
+
+        let longest_row_label = row_labels.iter().map(|label| label.width).max().unwrap_or(0);
+        let longest_column_label = column_labels.iter().map(|label| label.height).max().unwrap_or(0);
+
+        let dimension = PixelDimension {
+            width: metrics.window_margin
+                + grid.width
+                + metrics.arrow_spacing
+                + metrics.arrow_left.width
+                + metrics.label_spacing
+                + longest_row_label
+                + metrics.window_margin,
+            height: metrics.window_margin
+                + grid.height
+                + metrics.arrow_spacing
+                + metrics.arrow_down.height
+                + metrics.label_spacing
+                + longest_column_label
+                + metrics.window_margin,
+        };
+
+        Layout { metrics, grid, row_arrows, row_labels, column_arrows, column_labels, dimension }
+    }
+
+    pub fn rows(&self) -> usize {
+        self.row_arrows.len()
+    }
+
+    pub fn columns(&self) -> usize {
+        self.column_arrows.len()
+    }
+
+    pub fn cell(&self, column: usize, row: usize) -> PixelRect {
+        let pitch = self.metrics.button_pitch();
+
+        PixelRect {
+            x: self.grid.x + (column * pitch.width) as isize,
+            y: self.grid.y + (row * pitch.height) as isize,
+            width: self.metrics.button.width,
+            height: self.metrics.button.height,
+        }
+    }
+
+    // Rotated text is drawn starting from its bottom end
+    pub fn column_label_origin(&self, column: usize) -> PixelPosition {
+        let label = self.column_labels[column];
+        PixelPosition { x: label.x, y: label.y + label.height as isize }
+    }
+
+    pub fn cell_under_position(&self, position: PixelPosition) -> Option<(usize, usize)> {
+        if !self.grid.contains(position) {
+            return None;
+        }
+
+        let pitch = self.metrics.button_pitch();
+        let (px, py) = ((position.x - self.grid.x) as usize, (position.y - self.grid.y) as usize);
+        let (column, row) = (px / pitch.width, py / pitch.height);
+
+        // Clicks landing in the gaps between the buttons don't belong to any of them
+        if self.cell(column, row).contains(position) {
+            Some((column, row))
+        } else {
+            None
+        }
+    }
+
+    // The arrows belong to the labels they are pointing at
+    pub fn label_under_position(&self, position: PixelPosition) -> Option<LayoutLabel> {
+        let row = (0..self.rows())
+            .find(|&row| self.row_arrows[row].contains(position) || self.row_labels[row].contains(position));
+        let column = (0..self.columns()).find(|&column| {
+            self.column_arrows[column].contains(position) || self.column_labels[column].contains(position)
+        });
+
+        row.map(LayoutLabel::Row).or_else(|| column.map(LayoutLabel::Column))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metrics() -> LayoutMetrics {
+        LayoutMetrics {
+            window_margin: 8,
+            label_spacing: 4,
+            arrow_spacing: 2,
+            grid_spacing: 1,
+            button: PixelDimension { width: 16, height: 16 },
+            arrow_left: PixelDimension { width: 16, height: 16 },
+            arrow_down: PixelDimension { width: 16, height: 16 },
+            glyph: PixelDimension { width: 8, height: 12 },
+        }
+    }
+
+    fn labels(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn empty_layout() {
+        let layout = Layout::new(metrics(), &[], &[]);
+
+        assert_eq!((layout.grid.width, layout.grid.height), (0, 0));
+        assert_eq!(layout.dimension, PixelDimension { width: 8 + 2 + 16 + 4 + 8, height: 8 + 2 + 16 + 4 + 8 });
+        assert_eq!(layout.cell_under_position(PixelPosition { x: 8, y: 8 }), None);
+    }
+
+    #[test]
+    fn cells_are_spaced_by_the_grid_spacing() {
+        let layout = Layout::new(metrics(), &labels(&["a", "b"]), &labels(&["c", "d", "e"]));
+
+        assert_eq!(layout.grid, PixelRect { x: 8, y: 8, width: 3 * 17 - 1, height: 2 * 17 - 1 });
+        assert_eq!(layout.cell(0, 0), PixelRect { x: 8, y: 8, width: 16, height: 16 });
+        assert_eq!(layout.cell(2, 1), PixelRect { x: 8 + 34, y: 8 + 17, width: 16, height: 16 });
+    }
+
+    #[test]
+    fn arrows_and_labels_follow_the_grid() {
+        let layout = Layout::new(metrics(), &labels(&["out"]), &labels(&["input"]));
+
+        assert_eq!(layout.row_arrows[0], PixelRect { x: 8 + 16 + 2, y: 8, width: 16, height: 16 });
+        assert_eq!(layout.row_labels[0], PixelRect { x: 26 + 16 + 4, y: 8 + 2, width: 24, height: 12 });
+        assert_eq!(layout.column_arrows[0], PixelRect { x: 8, y: 8 + 16 + 2, width: 16, height: 16 });
+        assert_eq!(layout.column_labels[0], PixelRect { x: 8 + 2, y: 26 + 16 + 4, width: 12, height: 40 });
+        assert_eq!(layout.column_label_origin(0), PixelPosition { x: 10, y: 46 + 40 });
+    }
+
+    #[test]
+    fn dimension_fits_the_longest_labels() {
+        let layout = Layout::new(metrics(), &labels(&["a", "abcd"]), &labels(&["abc", "ab"]));
+
+        assert_eq!(layout.dimension.width, 8 + 33 + 2 + 16 + 4 + 4 * 8 + 8);
+        assert_eq!(layout.dimension.height, 8 + 33 + 2 + 16 + 4 + 3 * 8 + 8);
+    }
+
+    #[test]
+    fn label_lengths_count_characters() {
+        let layout = Layout::new(metrics(), &labels(&["Ünïcödé"]), &labels(&["Synthé"]));
+
+        assert_eq!(layout.row_labels[0].width, 7 * 8);
+        assert_eq!(layout.column_labels[0].height, 6 * 8);
+    }
+
+    #[test]
+    fn hit_testing() {
+        let layout = Layout::new(metrics(), &labels(&["a", "b"]), &labels(&["c", "d"]));
+
+        assert_eq!(layout.cell_under_position(PixelPosition { x: 8, y: 8 }), Some((0, 0)));
+        assert_eq!(layout.cell_under_position(PixelPosition { x: 8 + 17, y: 8 + 17 }), Some((1, 1)));
+        assert_eq!(layout.cell_under_position(PixelPosition { x: 8 + 32, y: 8 + 32 }), Some((1, 1)));
+
+        // Grid gaps, margins and the area past the last cell
+        assert_eq!(layout.cell_under_position(PixelPosition { x: 8 + 16, y: 8 }), None);
+        assert_eq!(layout.cell_under_position(PixelPosition { x: 7, y: 8 }), None);
+        assert_eq!(layout.cell_under_position(PixelPosition { x: 8 + 33, y: 8 }), None);
+        assert_eq!(layout.cell_under_position(PixelPosition { x: 8, y: 8 + 33 }), None);
+    }
+
+    #[test]
+    fn label_hit_testing() {
+        let layout = Layout::new(metrics(), &labels(&["out"]), &labels(&["input"]));
+
+        assert_eq!(layout.label_under_position(PixelPosition { x: 26, y: 8 }), Some(LayoutLabel::Row(0)));
+        assert_eq!(layout.label_under_position(PixelPosition { x: 46 + 23, y: 10 }), Some(LayoutLabel::Row(0)));
+        assert_eq!(layout.label_under_position(PixelPosition { x: 46 + 24, y: 10 }), None);
+        assert_eq!(layout.label_under_position(PixelPosition { x: 8, y: 26 }), Some(LayoutLabel::Column(0)));
+        assert_eq!(layout.label_under_position(PixelPosition { x: 10, y: 46 + 39 }), Some(LayoutLabel::Column(0)));
+        assert_eq!(layout.label_under_position(PixelPosition { x: 8, y: 8 }), None);
+    }
+}
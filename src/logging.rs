@@ -0,0 +1,177 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::config::{LogLevel, LoggingConfig};
+
+struct Logger {
+    config: LoggingConfig,
+    log_path: Option<PathBuf>,
+    file: Option<File>,
+    file_size: u64,
+}
+
+// Logging is used from every thread, including the ones that don't have access to the
+// application state.
+static LOGGER: Mutex<Option<Logger>> = Mutex::new(None);
+
+fn log_path() -> Option<PathBuf> {
+    Some(dirs::state_dir().or_else(dirs::data_local_dir)?.join("aseqmatrix").join("aseqmatrix.log"))
+}
+
+// `aseqmatrix.log` becomes `aseqmatrix.log.1`, `aseqmatrix.log.1` becomes `aseqmatrix.log.2`
+// and so on, the oldest one is dropped
+fn rotated_log_path(log_path: &Path, index: usize) -> PathBuf {
+    let mut file_name = log_path.file_name().unwrap_or_default().to_owned();
+    file_name.push(format!(".{}", index));
+    log_path.with_file_name(file_name)
+}
+
+fn open_log_file(log_path: &Path) -> Option<(File, u64)> {
+    fs::create_dir_all(log_path.parent()?).ok()?;
+    let file = OpenOptions::new().create(true).append(true).open(log_path).ok()?;
+    let file_size = file.metadata().ok()?.len();
+    Some((file, file_size))
+}
+
+// Formats the time as UTC, e.g. `2021-06-12T18:30:00.000Z`
+fn format_timestamp(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let (days, seconds) = ((since_epoch.as_secs() / 86400) as i64, since_epoch.as_secs() % 86400);
+
+    // Days to civil date, see http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+impl Logger {
+    fn new(config: &LoggingConfig) -> Logger {
+        // Not even created when it's turned off
+        let log_path = log_path().filter(|_| config.level != LogLevel::Off);
+        let (file, file_size) = match log_path.as_deref().and_then(open_log_file) {
+            Some((file, file_size)) => (Some(file), file_size),
+            None => (None, 0),
+        };
+
+        Logger { config: config.clone(), log_path, file, file_size }
+    }
+
+    fn rotate(&mut self) {
+        let log_path = match &self.log_path {
+            Some(log_path) => log_path.clone(),
+            None => return,
+        };
+
+        self.file = None;
+
+        for index in (1..self.config.rotated_files).rev() {
+            let _ = fs::rename(rotated_log_path(&log_path, index), rotated_log_path(&log_path, index + 1));
+        }
+
+        if self.config.rotated_files > 0 {
+            let _ = fs::rename(&log_path, rotated_log_path(&log_path, 1));
+        } else {
+            let _ = fs::remove_file(&log_path);
+        }
+
+        if let Some((file, file_size)) = open_log_file(&log_path) {
+            self.file = Some(file);
+            self.file_size = file_size;
+        }
+    }
+
+    fn write(&mut self, level: LogLevel, message: &str) {
+        let line = format!("{} {:<5} {}\n", format_timestamp(SystemTime::now()), level.caption(), message);
+
+        // Errors were always printed, they still are regardless of the config
+        if self.config.stderr || (level == LogLevel::Error) {
+            eprint!("{}", line);
+        }
+
+        // Errors get here even with the level set to `off`, that only turns off the file
+        if level > self.config.level {
+            return;
+        }
+
+        if self.file_size + line.len() as u64 > self.config.max_file_size {
+            self.rotate();
+        }
+
+        if let Some(file) = &mut self.file {
+            if file.write_all(line.as_bytes()).is_ok() {
+                self.file_size += line.len() as u64;
+            }
+        }
+    }
+}
+
+pub fn init(config: &LoggingConfig) {
+    *LOGGER.lock().unwrap() = Some(Logger::new(config));
+}
+
+// Callers check this before formatting expensive messages. Errors are always enabled,
+// they're printed to stderr even when the log file is turned off.
+pub fn enabled(level: LogLevel) -> bool {
+    match &*LOGGER.lock().unwrap() {
+        Some(logger) => (level == LogLevel::Error) || ((level != LogLevel::Off) && (level <= logger.config.level)),
+        None => level == LogLevel::Error,
+    }
+}
+
+pub fn log(level: LogLevel, message: &str) {
+    if !enabled(level) {
+        return;
+    }
+
+    match &mut *LOGGER.lock().unwrap() {
+        Some(logger) => logger.write(level, message),
+        // Nothing is set up yet when the config itself fails to load
+        None => eprintln!("{}", message),
+    }
+}
+
+pub fn error(message: &str) {
+    log(LogLevel::Error, message);
+}
+
+pub fn info(message: &str) {
+    log(LogLevel::Info, message);
+}
+
+pub fn debug(message: &str) {
+    log(LogLevel::Debug, message);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn timestamps_are_formatted_as_utc() {
+        assert_eq!(format_timestamp(UNIX_EPOCH), "1970-01-01T00:00:00.000Z");
+        assert_eq!(format_timestamp(UNIX_EPOCH + Duration::from_millis(1623522600250)), "2021-06-12T18:30:00.250Z");
+        assert_eq!(format_timestamp(UNIX_EPOCH + Duration::from_secs(951782400)), "2000-02-29T00:00:00.000Z");
+    }
+}
@@ -1,62 +1,1060 @@
-use std::ffi::CString;
+use std::collections::HashMap;
+use std::env;
+use std::path::Path;
+use std::sync::mpsc::Receiver;
 use std::sync::{Arc, Mutex};
+use std::time::Instant;
 use std::{thread, time};
 
-use alsa::seq::{
-    Addr, ClientIter, PortCap, PortInfo, PortIter, PortSubscribe, PortSubscribeIter, PortType, QuerySubsType, Seq,
-};
-use alsa::PollDescriptors;
+use alsa::seq::{Addr, Seq};
 use sdl2::event::{Event, WindowEvent};
-use sdl2::keyboard::Keycode;
+use sdl2::keyboard::{Keycode, Mod};
 use sdl2::mouse::MouseButton;
-use sdl2::render::Canvas;
-use sdl2::video::Window;
+use sdl2::pixels::Color;
+use sdl2::render::{Canvas, RenderTarget, TextureCreator};
+use sdl2::video::{Window, WindowContext};
+
+mod browser;
+use browser::ThemeBrowser;
+
+mod export;
+use export::{default_export_path, export_png, export_svg};
 
 mod graphics;
-use graphics::{draw_borders, draw_string, draw_tiled_background, draw_tiles, PixelDimension, PixelPosition};
+use graphics::{
+    draw_borders, draw_colored_string, draw_tiled_background, draw_tiles, fill_rect, PixelDimension, PixelPosition,
+    PixelRect, TileRect,
+};
+
+mod layout;
+use layout::{Layout, LayoutLabel, LayoutMetrics};
+
+mod menu;
+use menu::{ContextMenu, MenuAction, MenuItem, MenuTarget};
+
+mod prompt;
+use prompt::TextPrompt;
 
 mod theme;
-use theme::Theme;
+use theme::{Theme, ThemeColor, ThemeLabelStyle};
 
 mod error;
 use error::{sdl_error, Error};
 
 mod config;
-use config::AppConfig;
+use config::{AppConfig, LogLevel, SortMode};
+
+mod watcher;
+use watcher::{ThemeChangeEvent, ThemeWatchErrorEvent, ThemeWatcher};
+
+mod endpoints;
+use endpoints::{EndpointDelta, EndpointSnapshot, PortEntry};
+
+mod routing;
+use routing::{SubscriptionAttribute, SubscriptionAttributes};
+
+mod sequencer;
+use sequencer::{
+    CommandOutcome, MidiActivityEvent, MidiPortChangeEvent, SequencerCommand, SequencerHandle, SequencerReply,
+    SequencerReplyEvent,
+};
+
+mod status;
+use status::{StatusLevel, StatusMessage};
+
+mod toolbar;
+
+mod logging;
+
+mod snapshot;
+use snapshot::Snapshot;
+
+mod control;
+use control::{default_socket_path, ControlServer};
+
+mod osc;
+use osc::OscServer;
+
+mod bindings;
+use bindings::{BindingAction, MidiBinding, MidiTrigger};
+
+#[cfg(test)]
+mod golden_tests;
+#[cfg(test)]
+mod state_tests;
+
+const WINDOW_TITLE: &str = "ALSA Sequencer Matrix";
+
+// How long a monitored port stays lit after receiving an event
+const ACTIVITY_FLASH_DURATION: time::Duration = time::Duration::from_millis(150);
+
+// The state of the first pressed cell decides whether the drag connects or disconnects
+struct DragState {
+    anchor: (usize, usize),
+    // The cell the pointer was over at the previous motion event
+    last_cell: (usize, usize),
+    connect: bool,
+    painted: Vec<(usize, usize)>,
+    rubber_band: bool,
+}
+
+impl DragState {
+    fn rubber_band_cells(&self, corner: (usize, usize)) -> Vec<(usize, usize)> {
+        let (input_range, output_range) = (
+            self.anchor.0.min(corner.0)..=self.anchor.0.max(corner.0),
+            self.anchor.1.min(corner.1)..=self.anchor.1.max(corner.1),
+        );
+
+        output_range
+            .flat_map(|output_index| input_range.clone().map(move |input_index| (input_index, output_index)))
+            .collect()
+    }
+
+    // Fast drags skip cells between two motion events, the line between them is painted
+    // instead, walked along Bresenham's algorithm
+    fn line_cells(&self, to: (usize, usize)) -> Vec<(usize, usize)> {
+        let (mut x, mut y) = (self.last_cell.0 as isize, self.last_cell.1 as isize);
+        let (to_x, to_y) = (to.0 as isize, to.1 as isize);
+        let (delta_x, delta_y) = ((to_x - x).abs(), -(to_y - y).abs());
+        let (step_x, step_y) = ((to_x - x).signum(), (to_y - y).signum());
+        let mut error = delta_x + delta_y;
+
+        let mut cells = vec![(x as usize, y as usize)];
+        while (x, y) != (to_x, to_y) {
+            if 2 * error >= delta_y {
+                error += delta_y;
+                x += step_x;
+            }
+            if 2 * error <= delta_x {
+                error += delta_x;
+                y += step_y;
+            }
+            cells.push((x as usize, y as usize));
+        }
+
+        cells
+    }
+}
+
+struct AppState {
+    ports: Vec<PortEntry>,
+    inputs: Vec<(Addr, String)>,
+    outputs: Vec<(Addr, String)>,
+    connections: Vec<(Addr, Addr)>,
+    selection: Option<(usize, usize)>,
+    previous_selection: Option<(usize, usize)>,
+    selection_changed_at: Instant,
+    mouse_down: bool,
+    mouse_down_changed_at: Instant,
+    mouse_last_position: Option<PixelPosition>,
+    drag: Option<DragState>,
+    theme_browser: Option<ThemeBrowser>,
+    context_menu: Option<ContextMenu>,
+    prompt: Option<TextPrompt>,
+    port_keys: HashMap<Addr, String>,
+    own_client: Option<i32>,
+    monitored_ports: Vec<Addr>,
+    listened_ports: Vec<Addr>,
+    midi_learn: Option<BindingAction>,
+    current_snapshot: Option<String>,
+    port_activity: HashMap<Addr, Instant>,
+    layout: Layout,
+    sequencer_error: Option<String>,
+    theme_error: Option<String>,
+    status: Option<StatusMessage>,
+    // Exported images only show the matrix
+    show_toolbar: bool,
+    sequencer: Option<SequencerHandle>,
+    config: AppConfig,
+}
+
+impl AppState {
+    fn new() -> Result<AppState, Error> {
+        Ok(AppState::with_config(AppConfig::new()?))
+    }
+
+    fn with_config(config: AppConfig) -> AppState {
+        AppState {
+            ports: Vec::new(),
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+            connections: Vec::new(),
+            selection: None,
+            previous_selection: None,
+            selection_changed_at: Instant::now(),
+            mouse_down: false,
+            mouse_down_changed_at: Instant::now(),
+            mouse_last_position: None,
+            drag: None,
+            theme_browser: None,
+            context_menu: None,
+            prompt: None,
+            port_keys: HashMap::new(),
+            own_client: None,
+            monitored_ports: Vec::new(),
+            listened_ports: Vec::new(),
+            midi_learn: None,
+            current_snapshot: None,
+            port_activity: HashMap::new(),
+            layout: Layout::default(),
+            sequencer_error: None,
+            theme_error: None,
+            status: None,
+            show_toolbar: true,
+            sequencer: None,
+            config,
+        }
+    }
+
+    fn refresh_midi_endpoints(&mut self, seq: &Seq) -> Result<(), Error> {
+        self.apply_snapshot(EndpointSnapshot::query(seq)?);
+        Ok(())
+    }
+
+    fn apply_snapshot(&mut self, snapshot: EndpointSnapshot) {
+        self.ports = snapshot.ports;
+        self.connections =
+            snapshot.connections.into_iter().filter(|&connection| !self.connection_filtered(connection)).collect();
+        self.rebuild_endpoints();
+    }
+
+    // Returns whether the delta changed anything visible
+    fn apply_delta(&mut self, delta: EndpointDelta) -> bool {
+        match delta {
+            EndpointDelta::PortStart(port) => {
+                logging::info(&format!("Port started: {}", self.port_caption(port.addr, Some(&port))));
+                self.ports.retain(|known_port| known_port.addr != port.addr);
+                self.ports.push(port);
+                true
+            }
+            EndpointDelta::PortExit(port_addr) => {
+                logging::info(&format!("Port exited: {}", self.port_caption(port_addr, None)));
+                self.ports.retain(|known_port| known_port.addr != port_addr);
+                self.connections
+                    .retain(|&(input_addr, output_addr)| (input_addr != port_addr) && (output_addr != port_addr));
+                true
+            }
+            EndpointDelta::Subscribed(input_addr, output_addr) => {
+                let connection = (input_addr, output_addr);
+                if self.connection_filtered(connection) || self.connections.contains(&connection) {
+                    return false;
+                }
+
+                logging::info(&format!(
+                    "Subscription added: {} -> {}",
+                    self.port_caption(input_addr, None),
+                    self.port_caption(output_addr, None)
+                ));
+                self.connections.push(connection);
+                true
+            }
+            EndpointDelta::Unsubscribed(input_addr, output_addr) => {
+                let connection_count = self.connections.len();
+                self.connections.retain(|&connection| connection != (input_addr, output_addr));
+                if self.connections.len() == connection_count {
+                    return false;
+                }
+
+                logging::info(&format!(
+                    "Subscription removed: {} -> {}",
+                    self.port_caption(input_addr, None),
+                    self.port_caption(output_addr, None)
+                ));
+                true
+            }
+        }
+    }
+
+    // Ports are looked up either by their `client:port` addresses or by their keys
+    fn find_port(&self, port: &str) -> Option<Addr> {
+        let address = port.split_once(':').and_then(|(client, port)| Some((client.parse().ok()?, port.parse().ok()?)));
+
+        self.ports
+            .iter()
+            .find(|known_port| match address {
+                Some((client, port)) => known_port.addr == Addr { client, port },
+                None => known_port.key() == port,
+            })
+            .map(|known_port| known_port.addr)
+    }
+
+    // Snapshots only cover the connections shown in the matrix, the rest of the routing
+    // belongs to other applications
+    fn matrix_connections(&self) -> Vec<(Addr, Addr)> {
+        self.connections.iter().filter(|&&connection| self.matrix_connection(connection)).copied().collect()
+    }
+
+    fn matrix_connection(&self, (input_addr, output_addr): (Addr, Addr)) -> bool {
+        self.inputs.iter().any(|(addr, _)| *addr == input_addr)
+            && self.outputs.iter().any(|(addr, _)| *addr == output_addr)
+    }
+
+    // Unlike `port_name`, this covers the filtered and hidden ports too and ignores the aliases
+    fn port_caption(&self, port_addr: Addr, port: Option<&PortEntry>) -> String {
+        let port_key = port
+            .or_else(|| self.ports.iter().find(|known_port| known_port.addr == port_addr))
+            .map(PortEntry::key)
+            .unwrap_or_default();
+
+        format!("{} ({}:{})", port_key, port_addr.client, port_addr.port)
+    }
+
+    // Our own port only receives the events of the monitored ports, it's not part of the routing
+    fn connection_filtered(&self, (input_addr, output_addr): (Addr, Addr)) -> bool {
+        let client_filters = &self.config.client_filters;
+        client_filters.exclude_own_client
+            && ((Some(input_addr.client) == self.own_client) || (Some(output_addr.client) == self.own_client))
+    }
+
+    // The matrix is emptied instead of showing connections which may not exist anymore
+    fn sequencer_lost(&mut self, err: &Error) {
+        self.sequencer_error = Some(err.to_string());
+        self.status = Some(StatusMessage::error(&format!("Lost the sequencer, reconnecting: {}", err)));
+        self.own_client = None;
+        self.port_activity.clear();
+        self.apply_snapshot(EndpointSnapshot { ports: Vec::new(), connections: Vec::new() });
+    }
+
+    // Errors of user actions are not fatal, they end up in the toolbar
+    fn show_error(&mut self, context: &str, err: &Error) {
+        logging::error(&format!("{}: {}", context, err));
+        self.status = Some(StatusMessage::error(&format!("{}: {}", context, err)));
+    }
+
+    // While the theme browser is open, its previewed theme is shown instead of the configured one
+    fn theme_manifest_path(&self) -> &Path {
+        match &self.theme_browser {
+            Some(theme_browser) => theme_browser.previewed_manifest_path(),
+            None => &self.config.theme_manifest_path,
+        }
+    }
+
+    fn window_title(&self) -> String {
+        match (&self.sequencer_error, &self.theme_error) {
+            (Some(err), _) => format!("{} - Sequencer error: {} (reconnecting)", WINDOW_TITLE, err),
+            (None, Some(err)) => format!("{} - Theme error: {}", WINDOW_TITLE, err),
+            (None, None) => WINDOW_TITLE.to_owned(),
+        }
+    }
+
+    fn port_names_changed(&self, port_keys: &[(Addr, String)]) -> bool {
+        let mut known_port_keys =
+            self.ports.iter().map(|port| (port.addr, port.key())).collect::<Vec<(Addr, String)>>();
+        known_port_keys.sort();
+        known_port_keys != port_keys
+    }
+
+    // Derives the rows and columns from the known ports, no sequencer access is needed
+    fn rebuild_endpoints(&mut self) {
+        let previous_inputs = self.inputs.iter().map(|(port_addr, _)| *port_addr).collect::<Vec<Addr>>();
+        let previous_outputs = self.outputs.iter().map(|(port_addr, _)| *port_addr).collect::<Vec<Addr>>();
+
+        self.inputs.clear();
+        self.outputs.clear();
+        self.port_keys.clear();
+
+        for port in &self.ports {
+            let port_key = port.key();
+
+            // The connections of filtered and hidden ports are still kept for the feedback loop checks
+            let hidden = !self.config.client_filters.accepts(port.addr.client, &port.client_name, self.own_client)
+                || self.config.hidden_ports.contains(&port_key);
+
+            if port.readable() && !hidden {
+                self.inputs.push((port.addr, port.name.clone()));
+            }
+
+            if port.writable() && !hidden {
+                self.outputs.push((port.addr, port.name.clone()));
+            }
+
+            self.port_keys.insert(port.addr, port_key);
+        }
+
+        self.sort_ports(&previous_inputs, &previous_outputs);
+    }
+
+    fn update_layout(&mut self, metrics: LayoutMetrics) {
+        self.layout = Layout::new(metrics, &self.output_names(), &self.input_names());
+    }
+
+    // Snapshot of the routing state without any of the interactive overlays
+    fn export_state(&self) -> AppState {
+        let mut app = AppState::with_config(self.config.clone());
+        app.ports = self.ports.clone();
+        app.inputs = self.inputs.clone();
+        app.outputs = self.outputs.clone();
+        app.connections = self.connections.clone();
+        app.port_keys = self.port_keys.clone();
+        app.show_toolbar = false;
+        app.update_layout(self.layout.metrics);
+        app
+    }
+
+    fn cell_ports(&self, (input_index, output_index): (usize, usize)) -> Option<(Addr, Addr)> {
+        let (input_addr, output_addr) = (self.inputs.get(input_index)?.0, self.outputs.get(output_index)?.0);
+
+        if input_addr != output_addr {
+            Some((input_addr, output_addr))
+        } else {
+            None
+        }
+    }
+
+    fn cell_connected(&self, cell: (usize, usize)) -> bool {
+        self.cell_ports(cell).map(|connection| self.connections.contains(&connection)).unwrap_or(false)
+    }
+
+    fn send_command(&self, command: SequencerCommand) -> Result<(), Error> {
+        self.sequencer.as_ref().ok_or(Error::GeneralError("sequencer is not running"))?.send(command)
+    }
+
+    // The state must be unlocked before waiting for the outcome, the sequencer thread needs it
+    fn send_command_with_outcome(&self, command: SequencerCommand) -> Result<Receiver<CommandOutcome>, Error> {
+        self.sequencer.as_ref().ok_or(Error::GeneralError("sequencer is not running"))?.send_with_outcome(command)
+    }
+
+    fn save_snapshot(&self, name: &str) -> Result<(), Error> {
+        Snapshot::capture(&self.ports, &self.matrix_connections()).save(name)
+    }
+
+    fn load_snapshot(&self, name: &str) -> Result<(), Error> {
+        self.send_command(SequencerCommand::LoadSnapshot(name.to_owned(), Snapshot::load(name)?))
+    }
+
+    // Without a port, the panic is sent to every output port of the matrix
+    fn send_panic(&self, output_addr: Option<Addr>) -> Result<(), Error> {
+        let output_addrs = match output_addr {
+            Some(output_addr) => vec![output_addr],
+            None => self.outputs.iter().map(|(output_addr, _)| *output_addr).collect(),
+        };
+
+        if output_addrs.is_empty() {
+            return Err(Error::GeneralError("there are no output ports"));
+        }
+
+        self.send_command(SequencerCommand::Panic(output_addrs))
+    }
+
+    // Steps through the snapshots in the order of their names, wrapping around at the ends
+    fn stepped_snapshot(&self, names: &[String], forward: bool) -> Result<String, Error> {
+        if names.is_empty() {
+            return Err(Error::GeneralError("there are no snapshots"));
+        }
+
+        let current_index =
+            self.current_snapshot.as_ref().and_then(|current| names.iter().position(|name| name == current));
+        let index = match (current_index, forward) {
+            (Some(index), true) => (index + 1) % names.len(),
+            (Some(index), false) => (index + names.len() - 1) % names.len(),
+            (None, true) => 0,
+            (None, false) => names.len() - 1,
+        };
+
+        Ok(names[index].clone())
+    }
+
+    // Our port is subscribed to the monitored ports, the ports with MIDI bindings and, while
+    // a binding is being learned, to every readable port of the matrix
+    fn wanted_listened_ports(&self) -> Vec<Addr> {
+        let mut port_addrs = self.monitored_ports.clone();
+
+        for port in self.ports.iter().filter(|port| port.readable()) {
+            let bound = self.config.midi_bindings.iter().any(|binding| binding.port == port.key());
+            let learning =
+                self.midi_learn.is_some() && self.inputs.iter().any(|(input_addr, _)| *input_addr == port.addr);
+
+            if (bound || learning) && !port_addrs.contains(&port.addr) {
+                port_addrs.push(port.addr);
+            }
+        }
+
+        port_addrs
+    }
+
+    // While learning, the first trigger replaces the binding of the same port and trigger,
+    // otherwise the bound action is carried out. Returns the status of a learned binding.
+    // Called by the sequencer thread, the state is only locked while looking up the binding,
+    // the config and the snapshots are read and written after unlocking it.
+    fn handle_midi_trigger(
+        app: &Mutex<AppState>,
+        port_addr: Addr,
+        trigger: MidiTrigger,
+    ) -> Result<Option<StatusMessage>, Error> {
+        let action = {
+            let mut app = app.lock().unwrap();
+            let port_key = match app.port_keys.get(&port_addr) {
+                Some(port_key) => port_key.clone(),
+                None => return Ok(None),
+            };
+
+            if let Some(action) = app.midi_learn.take() {
+                let status =
+                    format!("Bound {} of {} to {}", trigger.caption(), app.port_name(port_addr), action.caption());
+
+                app.config.midi_bindings.retain(|binding| (binding.port != port_key) || (binding.trigger != trigger));
+                app.config.midi_bindings.push(MidiBinding { port: port_key, trigger, action });
+                let config = app.config.clone();
+                drop(app);
 
-struct MidiPortChangeEvent;
+                config.save()?;
+                return Ok(Some(StatusMessage::info(&status)));
+            }
+
+            let binding = app
+                .config
+                .midi_bindings
+                .iter()
+                .find(|binding| (binding.port == port_key) && (binding.trigger == trigger));
+            match binding {
+                Some(binding) => binding.action.clone(),
+                None => return Ok(None),
+            }
+        };
+
+        let snapshot_name = match action {
+            BindingAction::LoadSnapshot { name } => name,
+            BindingAction::ToggleConnection { sender, dest } => {
+                let app = app.lock().unwrap();
+                let connection = (
+                    app.find_port(&sender).ok_or(Error::GeneralError("unknown port"))?,
+                    app.find_port(&dest).ok_or(Error::GeneralError("unknown port"))?,
+                );
+
+                if app.connections.contains(&connection) {
+                    app.send_command(SequencerCommand::Disconnect(vec![connection]))?;
+                } else {
+                    app.send_command(SequencerCommand::Connect(vec![connection]))?;
+                }
+                return Ok(None);
+            }
+            BindingAction::NextSnapshot | BindingAction::PreviousSnapshot => {
+                let names = Snapshot::names()?;
+                app.lock().unwrap().stepped_snapshot(&names, action == BindingAction::NextSnapshot)?
+            }
+        };
+
+        let snapshot = Snapshot::load(&snapshot_name)?;
+        app.lock().unwrap().send_command(SequencerCommand::LoadSnapshot(snapshot_name, snapshot))?;
+        Ok(None)
+    }
+
+    fn apply_sequencer_reply(&mut self, reply: SequencerReply) {
+        match reply {
+            SequencerReply::Status(status) => self.status = Some(status),
+            SequencerReply::Attributes(input_addr, output_addr, attributes) => {
+                // The attribute editor may have been closed while waiting for the reply
+                let target = MenuTarget::Connection(input_addr, output_addr);
+                let position =
+                    self.context_menu.as_ref().filter(|menu| menu.target == target).map(ContextMenu::position);
+
+                if let Some(position) = position {
+                    self.context_menu = Some(ContextMenu::new(target, Self::attribute_items(attributes), position));
+                }
+            }
+        }
+    }
+
+    // The connections are changed by the sequencer thread, the matrix is updated when it replies
+    fn paint_cells(&mut self, drag: &mut DragState, cells: &[(usize, usize)]) -> Result<(), Error> {
+        let mut connections = Vec::new();
+
+        for &cell in cells {
+            if drag.painted.contains(&cell) {
+                continue;
+            }
+            drag.painted.push(cell);
+
+            if let Some(connection) = self.cell_ports(cell) {
+                if self.cell_connected(cell) != drag.connect {
+                    connections.push(connection);
+                }
+            }
+        }
+
+        if connections.is_empty() {
+            return Ok(());
+        }
+
+        if drag.connect {
+            self.send_command(SequencerCommand::Connect(connections))
+        } else {
+            self.send_command(SequencerCommand::Disconnect(connections))
+        }
+    }
+
+    fn connection_caption(&self, (input_addr, output_addr): (Addr, Addr)) -> String {
+        format!("{} -> {}", self.port_name(input_addr), self.port_name(output_addr))
+    }
+
+    fn routing_status(
+        &self,
+        connect: bool,
+        changed_connections: &[(Addr, Addr)],
+        removed_connections: &[(Addr, Addr)],
+        failed_connections: &[((Addr, Addr), Error)],
+    ) -> StatusMessage {
+        let (verb, failure_verb) = if connect { ("Connected", "connect") } else { ("Disconnected", "disconnect") };
+
+        let mut text = match changed_connections {
+            [] if !failed_connections.is_empty() => String::new(),
+            [connection] => format!("{} {}", verb, self.connection_caption(*connection)),
+            _ => format!("{} {} port pairs", verb, changed_connections.len()),
+        };
+
+        for &connection in removed_connections {
+            text.push_str(&format!(", removed {} to break a feedback loop", self.connection_caption(connection)));
+        }
+
+        if failed_connections.is_empty() {
+            return StatusMessage::info(&text);
+        }
+
+        let failures = failed_connections
+            .iter()
+            .map(|(connection, err)| format!("{}: {}", self.connection_caption(*connection), err))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        if text.is_empty() {
+            text = format!("Failed to {} {}", failure_verb, failures);
+        } else {
+            text.push_str(&format!(", failed to {} {}", failure_verb, failures));
+        }
+
+        StatusMessage::error(&text)
+    }
+
+    fn port_name(&self, port_addr: Addr) -> String {
+        let port_name = self
+            .inputs
+            .iter()
+            .chain(self.outputs.iter())
+            .find(|(addr, _)| *addr == port_addr)
+            .map(|(_, port_name)| self.port_alias(port_addr).unwrap_or(port_name).to_owned());
+
+        port_name.unwrap_or_else(|| format!("{}:{}", port_addr.client, port_addr.port))
+    }
+
+    fn port_active(&self, port_addr: Addr) -> bool {
+        self.port_activity.get(&port_addr).map(|activity| activity.elapsed() < ACTIVITY_FLASH_DURATION).unwrap_or(false)
+    }
+
+    fn context_menu_at(&self, position: PixelPosition) -> Option<ContextMenu> {
+        let target = match self.layout.cell_under_position(position) {
+            Some(cell) => {
+                let (input_addr, output_addr) = self.cell_ports(cell)?;
+                MenuTarget::Connection(input_addr, output_addr)
+            }
+            None => match self.layout.label_under_position(position)? {
+                LayoutLabel::Row(output_index) => MenuTarget::Output(self.outputs.get(output_index)?.0),
+                LayoutLabel::Column(input_index) => MenuTarget::Input(self.inputs.get(input_index)?.0),
+            },
+        };
+
+        Some(ContextMenu::new(target, self.context_menu_items(target), position))
+    }
+
+    fn context_menu_items(&self, target: MenuTarget) -> Vec<MenuItem> {
+        match target {
+            MenuTarget::Input(port_addr) | MenuTarget::Output(port_addr) => {
+                let mut items = vec![
+                    MenuItem::new("Disconnect all", MenuAction::DisconnectAll),
+                    MenuItem::new("Connect to all", MenuAction::ConnectToAll),
+                    MenuItem::new("Hide port", MenuAction::HidePort),
+                    MenuItem::new("Rename alias", MenuAction::RenameAlias),
+                    MenuItem::new(if self.port_pinned(port_addr) { "Unpin" } else { "Pin to top" }, MenuAction::Pin),
+                    MenuItem::new("Move up", MenuAction::MoveUp),
+                    MenuItem::new("Move down", MenuAction::MoveDown),
+                    MenuItem::new("Sort by...", MenuAction::SortMenu),
+                    MenuItem::new("MIDI bindings...", MenuAction::BindingsMenu),
+                ];
+
+                // Only readable ports can be monitored, the events sent to a port are not observable
+                if self.monitored_ports.contains(&port_addr) {
+                    items.push(MenuItem::new("Stop monitoring", MenuAction::Monitor));
+                } else if self.inputs.iter().any(|(input_addr, _)| *input_addr == port_addr) {
+                    items.push(MenuItem::new("Monitor", MenuAction::Monitor));
+                } else {
+                    items.push(MenuItem::disabled("Monitor"));
+                }
+
+                if self.outputs.iter().any(|(output_addr, _)| *output_addr == port_addr) {
+                    items.push(MenuItem::new("Send panic", MenuAction::Panic));
+                }
+
+                if !self.config.hidden_ports.is_empty() {
+                    items.push(MenuItem::new("Show hidden ports", MenuAction::ShowHiddenPorts));
+                }
+
+                items
+            }
+            MenuTarget::Connection(input_addr, output_addr) => {
+                let item = |caption, action| {
+                    if self.connections.contains(&(input_addr, output_addr)) {
+                        MenuItem::new(caption, action)
+                    } else {
+                        MenuItem::disabled(caption)
+                    }
+                };
+
+                vec![
+                    item("Toggle exclusive", MenuAction::ToggleExclusive),
+                    item("Edit subscription attributes", MenuAction::EditAttributes),
+                    MenuItem::new("Show route path", MenuAction::ShowRoutePath),
+                    MenuItem::new("Learn MIDI toggle", MenuAction::LearnToggle),
+                ]
+            }
+        }
+    }
+
+    fn attribute_items(attributes: SubscriptionAttributes) -> Vec<MenuItem> {
+        let checkbox = |checked| if checked { "[x]" } else { "[ ]" };
+
+        vec![
+            MenuItem::new(&format!("{} Exclusive", checkbox(attributes.exclusive)), MenuAction::ToggleExclusive),
+            MenuItem::new(
+                &format!("{} Update timestamps", checkbox(attributes.time_update)),
+                MenuAction::ToggleTimeUpdate,
+            ),
+            MenuItem::new(
+                &format!("{} Real-time timestamps", checkbox(attributes.time_real)),
+                MenuAction::ToggleTimeReal,
+            ),
+        ]
+    }
+
+    fn execute_menu_action(&mut self, menu: &ContextMenu, action: MenuAction) -> Result<(), Error> {
+        match (menu.target, action) {
+            (MenuTarget::Input(port_addr), MenuAction::DisconnectAll)
+            | (MenuTarget::Output(port_addr), MenuAction::DisconnectAll) => {
+                let port_connections = self
+                    .connections
+                    .iter()
+                    .filter(|(input_addr, output_addr)| match menu.target {
+                        MenuTarget::Input(_) => *input_addr == port_addr,
+                        _ => *output_addr == port_addr,
+                    })
+                    .copied()
+                    .collect::<Vec<(Addr, Addr)>>();
+
+                self.send_command(SequencerCommand::Disconnect(port_connections))?;
+            }
+            (MenuTarget::Input(port_addr), MenuAction::ConnectToAll)
+            | (MenuTarget::Output(port_addr), MenuAction::ConnectToAll) => {
+                let port_connections = match menu.target {
+                    MenuTarget::Input(_) => {
+                        self.outputs.iter().map(|(output_addr, _)| (port_addr, *output_addr)).collect::<Vec<_>>()
+                    }
+                    _ => self.inputs.iter().map(|(input_addr, _)| (*input_addr, port_addr)).collect::<Vec<_>>(),
+                };
+
+                let port_connections = port_connections
+                    .into_iter()
+                    .filter(|&(input_addr, output_addr)| {
+                        (input_addr != output_addr) && !self.connections.contains(&(input_addr, output_addr))
+                    })
+                    .collect::<Vec<_>>();
+
+                self.send_command(SequencerCommand::Connect(port_connections))?;
+            }
+            (MenuTarget::Input(port_addr), MenuAction::HidePort)
+            | (MenuTarget::Output(port_addr), MenuAction::HidePort) => {
+                if let Some(port_key) = self.port_keys.get(&port_addr) {
+                    self.config.hidden_ports.push(port_key.clone());
+                    self.config.save()?;
+                }
+
+                self.rebuild_endpoints();
+            }
+            (MenuTarget::Input(port_addr), MenuAction::Pin) | (MenuTarget::Output(port_addr), MenuAction::Pin) => {
+                if let Some(port_key) = self.port_keys.get(&port_addr).cloned() {
+                    if self.port_pinned(port_addr) {
+                        self.config.pinned_ports.retain(|pinned_port| *pinned_port != port_key);
+                    } else {
+                        self.config.pinned_ports.push(port_key);
+                    }
+
+                    self.config.save()?;
+                    self.sort_ports(&[], &[]);
+                }
+            }
+            (MenuTarget::Input(port_addr), MenuAction::MoveUp) => self.move_port(port_addr, true, -1)?,
+            (MenuTarget::Input(port_addr), MenuAction::MoveDown) => self.move_port(port_addr, true, 1)?,
+            (MenuTarget::Output(port_addr), MenuAction::MoveUp) => self.move_port(port_addr, false, -1)?,
+            (MenuTarget::Output(port_addr), MenuAction::MoveDown) => self.move_port(port_addr, false, 1)?,
+            (_, MenuAction::SortMenu) => {
+                let items = SortMode::ALL
+                    .iter()
+                    .map(|&sort_mode| {
+                        let marker = if sort_mode == self.config.sort_mode { "(*)" } else { "( )" };
+                        MenuItem::new(&format!("{} {}", marker, sort_mode.caption()), MenuAction::SortBy(sort_mode))
+                    })
+                    .collect();
+
+                self.context_menu = Some(ContextMenu::new(menu.target, items, menu.position()));
+            }
+            (_, MenuAction::SortBy(sort_mode)) => {
+                self.config.sort_mode = sort_mode;
+                self.config.save()?;
+                self.sort_ports(&[], &[]);
+            }
+            (MenuTarget::Input(port_addr), MenuAction::BindingsMenu)
+            | (MenuTarget::Output(port_addr), MenuAction::BindingsMenu) => {
+                let port_key = self.port_keys.get(&port_addr);
+                let port_bindings = self
+                    .config
+                    .midi_bindings
+                    .iter()
+                    .filter(|binding| Some(&binding.port) == port_key)
+                    .collect::<Vec<&MidiBinding>>();
+
+                let mut items = port_bindings
+                    .iter()
+                    .map(|binding| {
+                        MenuItem::info(&format!("{}: {}", binding.trigger.caption(), binding.action.caption()))
+                    })
+                    .collect::<Vec<MenuItem>>();
+                if !port_bindings.is_empty() {
+                    items.push(MenuItem::new("Clear the bindings of this port", MenuAction::ClearBindings));
+                }
+
+                // The port the learned binding belongs to is the one the trigger comes from
+                items.push(MenuItem::new("Learn: next snapshot", MenuAction::LearnNextSnapshot));
+                items.push(MenuItem::new("Learn: previous snapshot", MenuAction::LearnPreviousSnapshot));
+                for (snapshot_index, name) in Snapshot::names()?.iter().enumerate() {
+                    items.push(MenuItem::new(
+                        &format!("Learn: load {}", name),
+                        MenuAction::LearnSnapshot(snapshot_index),
+                    ));
+                }
+
+                self.context_menu = Some(ContextMenu::new(menu.target, items, menu.position()));
+            }
+            (_, MenuAction::LearnSnapshot(snapshot_index)) => {
+                let name = Snapshot::names()?
+                    .get(snapshot_index)
+                    .cloned()
+                    .ok_or(Error::GeneralError("snapshot no longer exists"))?;
+                self.send_command(SequencerCommand::LearnBinding(Some(BindingAction::LoadSnapshot { name })))?;
+            }
+            (_, MenuAction::LearnNextSnapshot) => {
+                self.send_command(SequencerCommand::LearnBinding(Some(BindingAction::NextSnapshot)))?;
+            }
+            (_, MenuAction::LearnPreviousSnapshot) => {
+                self.send_command(SequencerCommand::LearnBinding(Some(BindingAction::PreviousSnapshot)))?;
+            }
+            (MenuTarget::Connection(input_addr, output_addr), MenuAction::LearnToggle) => {
+                let port_key =
+                    |port_addr| self.port_keys.get(&port_addr).cloned().ok_or(Error::GeneralError("unknown port"));
+                let action =
+                    BindingAction::ToggleConnection { sender: port_key(input_addr)?, dest: port_key(output_addr)? };
+
+                self.send_command(SequencerCommand::LearnBinding(Some(action)))?;
+            }
+            (MenuTarget::Input(port_addr), MenuAction::ClearBindings)
+            | (MenuTarget::Output(port_addr), MenuAction::ClearBindings) => {
+                if let Some(port_key) = self.port_keys.get(&port_addr) {
+                    self.config.midi_bindings.retain(|binding| binding.port != *port_key);
+                    self.config.save()?;
+                }
+            }
+            (MenuTarget::Input(port_addr), MenuAction::Panic) | (MenuTarget::Output(port_addr), MenuAction::Panic) => {
+                self.send_panic(Some(port_addr))?;
+            }
+            (_, MenuAction::ShowHiddenPorts) => {
+                self.config.hidden_ports.clear();
+                self.config.save()?;
+                self.rebuild_endpoints();
+            }
+            (MenuTarget::Input(port_addr), MenuAction::RenameAlias)
+            | (MenuTarget::Output(port_addr), MenuAction::RenameAlias) => {
+                let caption = format!("Alias of {}:", self.port_name(port_addr));
+                self.prompt = Some(TextPrompt::new(port_addr, &caption, &self.port_name(port_addr), menu.position()));
+            }
+            (MenuTarget::Input(port_addr), MenuAction::Monitor)
+            | (MenuTarget::Output(port_addr), MenuAction::Monitor) => {
+                self.send_command(SequencerCommand::ToggleMonitor(port_addr))?;
+            }
+            (MenuTarget::Connection(input_addr, output_addr), MenuAction::EditAttributes) => {
+                self.send_command(SequencerCommand::QueryAttributes(input_addr, output_addr))?;
+
+                // Replaced by the attribute editor once the sequencer thread replies
+                let items = vec![MenuItem::info("Reading the attributes...")];
+                self.context_menu = Some(ContextMenu::new(menu.target, items, menu.position()));
+            }
+            (MenuTarget::Connection(input_addr, output_addr), MenuAction::ToggleExclusive)
+            | (MenuTarget::Connection(input_addr, output_addr), MenuAction::ToggleTimeUpdate)
+            | (MenuTarget::Connection(input_addr, output_addr), MenuAction::ToggleTimeReal) => {
+                let attribute = match action {
+                    MenuAction::ToggleExclusive => SubscriptionAttribute::Exclusive,
+                    MenuAction::ToggleTimeUpdate => SubscriptionAttribute::TimeUpdate,
+                    _ => SubscriptionAttribute::TimeReal,
+                };
+
+                self.send_command(SequencerCommand::ToggleAttribute(input_addr, output_addr, attribute))?;
+
+                // The attribute editor reopens for further changes and to show the outcome
+                let items = vec![MenuItem::info("Updating the attributes...")];
+                self.context_menu = Some(ContextMenu::new(menu.target, items, menu.position()));
+            }
+            (MenuTarget::Connection(input_addr, output_addr), MenuAction::ShowRoutePath) => {
+                let items = match routing::route_path(&self.connections, input_addr, output_addr) {
+                    Some(path) => path
+                        .iter()
+                        .enumerate()
+                        .map(|(path_index, &port_addr)| {
+                            let arrow = if path_index == 0 { "  " } else { "> " };
+                            MenuItem::info(&format!("{}{}", arrow, self.port_name(port_addr)))
+                        })
+                        .collect(),
+                    None => vec![MenuItem::info("No route between the ports")],
+                };
+
+                self.context_menu = Some(ContextMenu::new(menu.target, items, menu.position()));
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    fn commit_prompt(&mut self) -> Result<(), Error> {
+        if let Some(prompt) = self.prompt.take() {
+            let port_key = match self.port_keys.get(&prompt.target) {
+                Some(port_key) => port_key.clone(),
+                None => return Ok(()),
+            };
+
+            // An empty alias restores the original name
+            let alias = prompt.text.trim();
+            if alias.is_empty() {
+                self.config.aliases.remove(&port_key);
+            } else {
+                self.config.aliases.insert(port_key, alias.to_owned());
+            }
+
+            self.config.save()?;
+            self.sort_ports(&[], &[]);
+        }
+
+        Ok(())
+    }
+
+    fn port_pinned(&self, port_addr: Addr) -> bool {
+        self.port_keys.get(&port_addr).map(|port_key| self.config.pinned_ports.contains(port_key)).unwrap_or(false)
+    }
+
+    // Pinned ports come first, in the order they were pinned. While a drag is in progress
+    // the previous order is kept, cells jumping around would be painted by accident.
+    fn port_sort_key(&self, port_addr: Addr, is_input: bool, previous_order: &[Addr]) -> (usize, usize, String, Addr) {
+        if self.mouse_down && !previous_order.is_empty() {
+            let previous_index = previous_order.iter().position(|addr| *addr == port_addr).unwrap_or(usize::MAX);
+            return (0, previous_index, String::new(), port_addr);
+        }
+
+        let port_key = self.port_keys.get(&port_addr);
+        let index_in = |keys: &[String]| {
+            port_key.and_then(|port_key| keys.iter().position(|key| key == port_key)).unwrap_or(usize::MAX)
+        };
+
+        let pin_index = index_in(&self.config.pinned_ports);
+
+        match self.config.sort_mode {
+            SortMode::ClientId => (pin_index, 0, String::new(), port_addr),
+            SortMode::Name => (pin_index, 0, self.port_name(port_addr).to_lowercase(), port_addr),
+            SortMode::UserDefined => {
+                let port_order = if is_input { &self.config.input_port_order } else { &self.config.output_port_order };
+                (pin_index, index_in(port_order), String::new(), port_addr)
+            }
+            SortMode::ConnectedFirst => {
+                let connected = self.connections.iter().any(|&(input_addr, output_addr)| {
+                    if is_input {
+                        input_addr == port_addr
+                    } else {
+                        output_addr == port_addr
+                    }
+                });
+
+                (pin_index, if connected { 0 } else { 1 }, String::new(), port_addr)
+            }
+        }
+    }
+
+    fn sort_ports(&mut self, previous_inputs: &[Addr], previous_outputs: &[Addr]) {
+        let mut inputs = std::mem::take(&mut self.inputs);
+        inputs.sort_by_cached_key(|(port_addr, _)| self.port_sort_key(*port_addr, true, previous_inputs));
+        self.inputs = inputs;
+
+        let mut outputs = std::mem::take(&mut self.outputs);
+        outputs.sort_by_cached_key(|(port_addr, _)| self.port_sort_key(*port_addr, false, previous_outputs));
+        self.outputs = outputs;
+
+        self.update_layout(self.layout.metrics);
+    }
+
+    fn move_port(&mut self, port_addr: Addr, is_input: bool, offset: isize) -> Result<(), Error> {
+        self.reorder_port(port_addr, is_input, offset);
+        self.config.save()
+    }
+
+    // Ports are moved within their own group, pinned ones stay in front of the rest. Moving
+    // an unpinned port switches to the user-defined order, starting out from the current one.
+    fn reorder_port(&mut self, port_addr: Addr, is_input: bool, offset: isize) {
+        let ports = if is_input { &self.inputs } else { &self.outputs };
+        let port_pinned = self.port_pinned(port_addr);
+        let mut port_keys = ports
+            .iter()
+            .filter(|(addr, _)| self.port_pinned(*addr) == port_pinned)
+            .filter_map(|(addr, _)| self.port_keys.get(addr).cloned())
+            .collect::<Vec<String>>();
+
+        let port_index = match self.port_keys.get(&port_addr).and_then(|key| port_keys.iter().position(|k| k == key)) {
+            Some(port_index) => port_index,
+            None => return,
+        };
+
+        let port_key = port_keys.remove(port_index);
+        let new_index = (port_index as isize + offset).clamp(0, port_keys.len() as isize) as usize;
+        port_keys.insert(new_index, port_key);
+
+        if port_pinned {
+            // The moved ports take over each other's places, pins of absent ports are kept
+            let mut pin_indices = port_keys
+                .iter()
+                .filter_map(|key| self.config.pinned_ports.iter().position(|k| k == key))
+                .collect::<Vec<usize>>();
+            pin_indices.sort_unstable();
+
+            for (pin_index, port_key) in pin_indices.into_iter().zip(port_keys) {
+                self.config.pinned_ports[pin_index] = port_key;
+            }
+        } else {
+            // Ports currently not present keep their place at the front
+            let port_order =
+                if is_input { &mut self.config.input_port_order } else { &mut self.config.output_port_order };
+            port_order.retain(|key| !port_keys.contains(key));
+            port_order.extend(port_keys);
+            self.config.sort_mode = SortMode::UserDefined;
+        }
 
-struct AppState {
-    inputs: Vec<(Addr, String)>,
-    outputs: Vec<(Addr, String)>,
-    connections: Vec<(Addr, Addr)>,
-    selection: Option<(usize, usize)>,
-    mouse_down: bool,
-    mouse_last_position: Option<PixelPosition>,
-    config: AppConfig,
-}
+        self.sort_ports(&[], &[]);
+    }
 
-impl AppState {
-    fn new() -> Result<AppState, Error> {
-        Ok(AppState {
-            inputs: Vec::new(),
-            outputs: Vec::new(),
-            connections: Vec::new(),
-            selection: None,
-            mouse_down: false,
-            mouse_last_position: None,
-            config: AppConfig::new()?,
-        })
+    fn port_alias(&self, port_addr: Addr) -> Option<&str> {
+        let port_key = self.port_keys.get(&port_addr)?;
+        self.config.aliases.get(port_key).map(String::as_str)
     }
 
     fn input_names(&self) -> Vec<String> {
         self.inputs
             .iter()
             .map(|(port_addr, port_name)| {
+                let port_name = self.port_alias(*port_addr).unwrap_or(port_name);
                 if self.config.show_addresses {
                     format!("{} {:>3}:{}", port_name, port_addr.client, port_addr.port)
                 } else {
-                    port_name.clone()
+                    port_name.to_owned()
                 }
             })
             .collect()
@@ -66,74 +1064,97 @@ impl AppState {
         self.outputs
             .iter()
             .map(|(port_addr, port_name)| {
+                let port_name = self.port_alias(*port_addr).unwrap_or(port_name);
                 if self.config.show_addresses {
                     format!("{:>3}:{} {}", port_addr.client, port_addr.port, port_name)
                 } else {
-                    port_name.clone()
+                    port_name.to_owned()
                 }
             })
             .collect()
     }
 
-    fn render(&self, canvas: &mut Canvas<Window>, theme: &Theme) -> Result<(), Error> {
+    fn render<T: RenderTarget>(&self, canvas: &mut Canvas<T>, theme: &mut Theme) -> Result<(), Error> {
         draw_tiled_background(canvas, &theme.background_texture)?;
         draw_borders(canvas, &theme.borders_texture)?;
 
-        let button_dimensions = PixelDimension {
-            width: theme.controls_texture.tile_size.width * 2,
-            height: theme.controls_texture.tile_size.height * 2,
-        };
+        if let Some(ThemeColor(row_shading)) = theme.manifest.shading.rows {
+            for output_index in (1..self.layout.rows()).step_by(2) {
+                let cell = self.layout.cell(0, output_index);
+                fill_rect(
+                    canvas,
+                    cell.position(),
+                    PixelDimension {
+                        width: self.layout.dimension.width.saturating_sub(2 * self.layout.metrics.window_margin),
+                        height: cell.height,
+                    },
+                    row_shading,
+                )?;
+            }
+        }
 
-        let (horizontal_arrow_width, vertical_arrow_height) =
-            (theme.controls_texture.tile_size.width as isize, theme.controls_texture.tile_size.height as isize);
+        if let Some(ThemeColor(column_shading)) = theme.manifest.shading.columns {
+            for input_index in (1..self.layout.columns()).step_by(2) {
+                let cell = self.layout.cell(input_index, 0);
+                fill_rect(
+                    canvas,
+                    cell.position(),
+                    PixelDimension {
+                        width: cell.width,
+                        height: self.layout.dimension.height.saturating_sub(2 * self.layout.metrics.window_margin),
+                    },
+                    column_shading,
+                )?;
+            }
+        }
 
         for (output_index, output_name) in self.output_names().iter().enumerate() {
             let arrow_source = match self.selection {
-                Some((_, selection_y)) if selection_y == output_index => Theme::RECT_ARROW_LEFT_ACTIVE,
-                _ => Theme::RECT_ARROW_LEFT_NORMAL,
+                Some((_, selection_y)) if selection_y == output_index => theme.manifest.controls.arrow_left_active,
+                _ => theme.manifest.controls.arrow_left_normal,
             };
 
-            let arrow_position = PixelPosition {
-                x: theme.manifest.metrics.window_margin as isize
-                    + self.inputs.len() as isize * button_dimensions.width as isize,
-                y: theme.manifest.metrics.window_margin as isize
-                    + output_index as isize * button_dimensions.height as isize,
-            };
+            let arrow_source = self
+                .arrow_transition(
+                    theme,
+                    &theme.manifest.animations.arrow_left_active,
+                    self.selection.map(|(_, selection_y)| selection_y == output_index),
+                    self.previous_selection.map(|(_, selection_y)| selection_y == output_index),
+                )
+                .unwrap_or(arrow_source);
 
-            let text_position = PixelPosition {
-                x: arrow_position.x + horizontal_arrow_width + theme.manifest.metrics.label_spacing as isize,
-                y: arrow_position.y
-                    + (button_dimensions.height as isize - theme.font_texture.tile_size.height as isize) / 2,
-            };
+            let arrow_position = self.layout.row_arrows[output_index].position();
+            let label_rect = self.layout.row_labels[output_index];
+            let hovered = matches!(self.selection, Some((_, y)) if y == output_index);
 
             draw_tiles(canvas, &theme.controls_texture, arrow_source, arrow_position)?;
-            draw_string(canvas, &theme.font_texture, output_name, text_position, 0)?;
+            self.draw_label(canvas, theme, output_name, label_rect, 0, hovered)?;
         }
 
         for (input_index, input_name) in self.input_names().iter().enumerate() {
             let arrow_source = match self.selection {
-                Some((selection_x, _)) if selection_x == input_index => Theme::RECT_ARROW_DOWN_ACTIVE,
-                _ => Theme::RECT_ARROW_DOWN_NORMAL,
+                Some((selection_x, _)) if selection_x == input_index => theme.manifest.controls.arrow_down_active,
+                _ if self.port_active(self.inputs[input_index].0) => {
+                    theme.manifest.controls.arrow_down_activity.unwrap_or(theme.manifest.controls.arrow_down_active)
+                }
+                _ => theme.manifest.controls.arrow_down_normal,
             };
 
-            let arrow_position = PixelPosition {
-                x: theme.manifest.metrics.window_margin as isize
-                    + input_index as isize * button_dimensions.width as isize,
-                y: theme.manifest.metrics.window_margin as isize
-                    + self.outputs.len() as isize * button_dimensions.height as isize,
-            };
+            let arrow_source = self
+                .arrow_transition(
+                    theme,
+                    &theme.manifest.animations.arrow_down_active,
+                    self.selection.map(|(selection_x, _)| selection_x == input_index),
+                    self.previous_selection.map(|(selection_x, _)| selection_x == input_index),
+                )
+                .unwrap_or(arrow_source);
 
-            let text_position = PixelPosition {
-                x: arrow_position.x
-                    + (button_dimensions.width as isize - theme.font_texture.tile_size.height as isize) / 2,
-                y: arrow_position.y
-                    + vertical_arrow_height
-                    + theme.manifest.metrics.label_spacing as isize
-                    + input_name.len() as isize * theme.font_texture.tile_size.width as isize,
-            };
+            let arrow_position = self.layout.column_arrows[input_index].position();
+            let label_rect = self.layout.column_labels[input_index];
+            let hovered = matches!(self.selection, Some((x, _)) if x == input_index);
 
             draw_tiles(canvas, &theme.controls_texture, arrow_source, arrow_position)?;
-            draw_string(canvas, &theme.font_texture, input_name, text_position, 3)?;
+            self.draw_label(canvas, theme, input_name, label_rect, 3, hovered)?;
         }
 
         for (output_index, (output_addr, _)) in self.outputs.iter().enumerate() {
@@ -144,49 +1165,223 @@ impl AppState {
 
                 let button_source = match (input_addr == output_addr, has_connection, currently_down, currently_hovered)
                 {
-                    (true, _, false, true) => Theme::RECT_BUTTON_DISABLED_HOVER,
-                    (true, _, true, true) => Theme::RECT_BUTTON_DISABLED_DOWN,
-                    (true, _, _, _) => Theme::RECT_BUTTON_DISABLED,
-                    (false, false, false, true) => Theme::RECT_BUTTON_NORMAL_HOVER,
-                    (false, false, true, true) => Theme::RECT_BUTTON_NORMAL_DOWN,
-                    (false, false, _, _) => Theme::RECT_BUTTON_NORMAL,
-                    (false, true, false, true) => Theme::RECT_BUTTON_ACTIVE_HOVER,
-                    (false, true, true, true) => Theme::RECT_BUTTON_ACTIVE_DOWN,
-                    (false, true, _, _) => Theme::RECT_BUTTON_ACTIVE,
+                    (true, _, false, true) => theme.manifest.controls.button_disabled_hover,
+                    (true, _, true, true) => theme.manifest.controls.button_disabled_down,
+                    (true, _, _, _) => theme.manifest.controls.button_disabled,
+                    (false, false, false, true) => theme.manifest.controls.button_normal_hover,
+                    (false, false, true, true) => theme.manifest.controls.button_normal_down,
+                    (false, false, _, _) => theme.manifest.controls.button_normal,
+                    (false, true, false, true) => theme.manifest.controls.button_active_hover,
+                    (false, true, true, true) => theme.manifest.controls.button_active_down,
+                    (false, true, _, _) => theme.manifest.controls.button_active,
+                };
+
+                let animations = &theme.manifest.animations;
+                let (hover_frames, down_frames) = match (input_addr == output_addr, has_connection) {
+                    (true, _) => (&animations.button_disabled_hover, &animations.button_disabled_down),
+                    (false, false) => (&animations.button_normal_hover, &animations.button_normal_down),
+                    (false, true) => (&animations.button_active_hover, &animations.button_active_down),
                 };
 
-                let button_position = PixelPosition {
-                    x: theme.manifest.metrics.window_margin as isize
-                        + input_index as isize * button_dimensions.width as isize,
-                    y: theme.manifest.metrics.window_margin as isize
-                        + output_index as isize * button_dimensions.height as isize,
+                let button_transition = if currently_down {
+                    animations.frame(down_frames, self.mouse_down_changed_at, false)
+                } else if currently_hovered && (self.mouse_down_changed_at > self.selection_changed_at) {
+                    animations.frame(down_frames, self.mouse_down_changed_at, true)
+                } else if currently_hovered {
+                    animations.frame(hover_frames, self.selection_changed_at, false)
+                } else if self.previous_selection == Some((input_index, output_index)) {
+                    animations.frame(hover_frames, self.selection_changed_at, true)
+                } else {
+                    None
                 };
 
+                let button_source = button_transition.unwrap_or(button_source);
+
+                let button_position = self.layout.cell(input_index, output_index).position();
+
                 draw_tiles(canvas, &theme.controls_texture, button_source, button_position)?;
             }
         }
 
+        if let (Some(drag), Some(selection)) = (&self.drag, self.selection) {
+            if drag.rubber_band {
+                for (input_index, output_index) in drag.rubber_band_cells(selection) {
+                    let cell = self.layout.cell(input_index, output_index);
+                    draw_crosshair_cell(canvas, theme, None, cell)?;
+                }
+            }
+        }
+
+        if let (true, Some((selection_x, selection_y))) = (self.config.show_crosshair, self.selection) {
+            for input_index in (0..self.layout.columns()).filter(|&input_index| input_index != selection_x) {
+                let cell = self.layout.cell(input_index, selection_y);
+                draw_crosshair_cell(canvas, theme, theme.manifest.controls.crosshair_row, cell)?;
+            }
+
+            for output_index in (0..self.layout.rows()).filter(|&output_index| output_index != selection_y) {
+                let cell = self.layout.cell(selection_x, output_index);
+                draw_crosshair_cell(canvas, theme, theme.manifest.controls.crosshair_column, cell)?;
+            }
+        }
+
+        if let Some(toolbar_rect) = self.toolbar_rect(theme) {
+            toolbar::render(canvas, theme, toolbar_rect, self.status.as_ref())?;
+        }
+
+        if let Some(theme_browser) = &self.theme_browser {
+            theme_browser.render(canvas, theme, self.theme_browser_position(theme))?;
+        }
+
+        if let Some(context_menu) = &self.context_menu {
+            context_menu.render(canvas, theme)?;
+        }
+
+        if let Some(prompt) = &self.prompt {
+            prompt.render(canvas, theme)?;
+        }
+
         canvas.present();
         Ok(())
     }
 
-    fn resize_window(&mut self, canvas: &mut Canvas<Window>, theme: &Theme) -> Result<(), Error> {
-        let window_width = theme.manifest.metrics.window_margin
-            + self.inputs.len() * (2 * theme.controls_texture.tile_size.width) // Controls
-            + theme.controls_texture.tile_size.width // Arrow
-            + theme.manifest.metrics.label_spacing
-            + self.output_names().iter().map(String::len).max().unwrap_or(0) * (theme.font_texture.tile_size.width)
-            + theme.manifest.metrics.window_margin;
+    fn arrow_transition(
+        &self,
+        theme: &Theme,
+        frames: &[TileRect],
+        currently_active: Option<bool>,
+        previously_active: Option<bool>,
+    ) -> Option<TileRect> {
+        // Moving along the same row/column keeps the arrow lit, there's nothing to animate then
+        match (currently_active.unwrap_or(false), previously_active.unwrap_or(false)) {
+            (true, false) => theme.manifest.animations.frame(frames, self.selection_changed_at, false),
+            (false, true) => theme.manifest.animations.frame(frames, self.selection_changed_at, true),
+            _ => None,
+        }
+    }
+
+    fn set_mouse_down(&mut self, mouse_down: bool) {
+        self.mouse_down = mouse_down;
+        self.mouse_down_changed_at = Instant::now();
+    }
+
+    // The clock keeps running for one more frame after the animations and flashes end, so that
+    // their final state gets rendered too
+    fn animation_timeout(&self, theme: &Theme) -> Option<time::Duration> {
+        let frame_duration = theme.manifest.animations.frame_duration();
+        let last_change = self.selection_changed_at.max(self.mouse_down_changed_at);
+        let flashing =
+            self.port_activity.values().any(|activity| activity.elapsed() < ACTIVITY_FLASH_DURATION + frame_duration);
+
+        if (last_change.elapsed() < theme.manifest.animations.longest_duration() + frame_duration) || flashing {
+            Some(frame_duration)
+        } else {
+            None
+        }
+    }
+
+    fn draw_label<T: RenderTarget>(
+        &self,
+        canvas: &mut Canvas<T>,
+        theme: &mut Theme,
+        text: &str,
+        rect: PixelRect,
+        rotation: usize,
+        hovered: bool,
+    ) -> Result<(), Error> {
+        // Rotated text is drawn starting from its bottom end
+        let origin = match rotation {
+            0 => rect.position(),
+            _ => PixelPosition { x: rect.x, y: rect.y + rect.height as isize },
+        };
+
+        let text_color = self.label_color(theme, hovered);
+
+        if !(hovered && self.config.show_crosshair) {
+            return draw_colored_string(canvas, &mut theme.font_texture, text, origin, rotation, text_color);
+        }
+
+        match theme.manifest.crosshair.label_style {
+            ThemeLabelStyle::Bold => {
+                // Overdrawing the text shifted by a pixel along its direction
+                let shifted_origin = match rotation {
+                    0 => PixelPosition { x: origin.x + 1, y: origin.y },
+                    _ => PixelPosition { x: origin.x, y: origin.y - 1 },
+                };
+
+                draw_colored_string(canvas, &mut theme.font_texture, text, origin, rotation, text_color)?;
+                draw_colored_string(canvas, &mut theme.font_texture, text, shifted_origin, rotation, text_color)
+            }
+            ThemeLabelStyle::Inverted => {
+                let ThemeColor(inverted_color) = theme.manifest.crosshair.inverted_text;
+
+                fill_rect(
+                    canvas,
+                    rect.position(),
+                    PixelDimension { width: rect.width, height: rect.height },
+                    text_color,
+                )?;
+                draw_colored_string(canvas, &mut theme.font_texture, text, origin, rotation, inverted_color)
+            }
+        }
+    }
+
+    fn label_color(&self, theme: &Theme, hovered: bool) -> Color {
+        theme.manifest.label_colors.color(hovered, self.mouse_down)
+    }
+
+    fn theme_browser_position(&self, theme: &Theme) -> PixelPosition {
+        PixelPosition {
+            x: theme.manifest.metrics.window_margin as isize,
+            y: theme.manifest.metrics.window_margin as isize,
+        }
+    }
+
+    // The toolbar is placed below the matrix
+    fn toolbar_rect(&self, theme: &Theme) -> Option<PixelRect> {
+        let window_margin = self.layout.metrics.window_margin;
+
+        self.show_toolbar.then(|| PixelRect {
+            x: window_margin as isize,
+            y: self.layout.dimension.height as isize,
+            width: self.layout.dimension.width.saturating_sub(2 * window_margin),
+            height: toolbar::height(theme),
+        })
+    }
+
+    fn window_dimension(&self, theme: &Theme) -> PixelDimension {
+        let mut window_width = self.layout.dimension.width;
+        let mut window_height = self.layout.dimension.height;
+
+        if let Some(toolbar_rect) = self.toolbar_rect(theme) {
+            window_height += toolbar_rect.height + self.layout.metrics.window_margin;
+        }
+
+        if let Some(theme_browser) = &self.theme_browser {
+            let theme_browser_dimension = theme_browser.dimension(theme);
+            window_width = window_width.max(theme_browser_dimension.width + 2 * theme.manifest.metrics.window_margin);
+            window_height =
+                window_height.max(theme_browser_dimension.height + 2 * theme.manifest.metrics.window_margin);
+        }
+
+        let overlays = [
+            self.context_menu.as_ref().map(|context_menu| (context_menu.position(), context_menu.dimension(theme))),
+            self.prompt.as_ref().map(|prompt| (prompt.position(), prompt.dimension(theme))),
+        ];
+
+        for (position, dimension) in overlays.into_iter().flatten() {
+            window_width = window_width.max(position.x as usize + dimension.width);
+            window_height = window_height.max(position.y as usize + dimension.height);
+        }
+
+        PixelDimension { width: window_width, height: window_height }
+    }
 
-        let window_height = theme.manifest.metrics.window_margin
-            + self.outputs.len() * (2 * theme.controls_texture.tile_size.height) // Controls
-            + theme.controls_texture.tile_size.height // Arrow
-            + theme.manifest.metrics.label_spacing
-            + self.input_names().iter().map(String::len).max().unwrap_or(0) * (theme.font_texture.tile_size.width)
-            + theme.manifest.metrics.window_margin;
+    fn resize_window(&mut self, canvas: &mut Canvas<Window>, theme: &mut Theme) -> Result<(), Error> {
+        self.update_layout(LayoutMetrics::from_theme(theme));
+        let window_dimension = self.window_dimension(theme);
 
         let window = canvas.window_mut();
-        window.set_size(window_width as u32, window_height as u32)?;
+        window.set_size(window_dimension.width as u32, window_dimension.height as u32)?;
 
         if let Some(mouse_last_position) = self.mouse_last_position {
             self.update_selection(canvas, theme, mouse_last_position, false)?;
@@ -198,37 +1393,28 @@ impl AppState {
         Ok(())
     }
 
-    fn control_under_position(&self, theme: &Theme, position: PixelPosition) -> Option<(usize, usize)> {
-        let (px, py) = (
-            position.x - theme.manifest.metrics.window_margin as isize,
-            position.y - theme.manifest.metrics.window_margin as isize,
-        );
-
-        if (px < 0) || (py < 0) {
+    fn control_under_position(&self, position: PixelPosition) -> Option<(usize, usize)> {
+        if self.theme_browser.is_some() || self.context_menu.is_some() || self.prompt.is_some() {
             return None;
         }
 
-        let (control_x, control_y) = (
-            px as usize / (theme.controls_texture.tile_size.width * 2),
-            py as usize / (theme.controls_texture.tile_size.height * 2),
-        );
-
-        if (control_x < self.inputs.len()) && (control_y < self.outputs.len()) {
-            Some((control_x, control_y))
-        } else {
-            None
-        }
+        self.layout.cell_under_position(position)
     }
 
     fn update_selection(
         &mut self,
         canvas: &mut Canvas<Window>,
-        theme: &Theme,
+        theme: &mut Theme,
         position: PixelPosition,
         force_redraw: bool,
     ) -> Result<(), Error> {
         let last_selection = self.selection;
-        self.selection = self.control_under_position(theme, position);
+        self.selection = self.control_under_position(position);
+
+        if self.selection != last_selection {
+            self.previous_selection = last_selection;
+            self.selection_changed_at = Instant::now();
+        }
 
         if (self.selection != last_selection) || force_redraw {
             self.render(canvas, theme)?;
@@ -238,8 +1424,108 @@ impl AppState {
     }
 }
 
+fn draw_crosshair_cell<T: RenderTarget>(
+    canvas: &mut Canvas<T>,
+    theme: &Theme,
+    sprite: Option<TileRect>,
+    cell: PixelRect,
+) -> Result<(), Error> {
+    match sprite {
+        Some(sprite) => draw_tiles(canvas, &theme.controls_texture, sprite, cell.position()),
+        None => {
+            let ThemeColor(tint) = theme.manifest.crosshair.tint;
+            fill_rect(canvas, cell.position(), PixelDimension { width: cell.width, height: cell.height }, tint)
+        }
+    }
+}
+
+fn reload_theme<'a>(
+    app: &mut AppState,
+    canvas: &mut Canvas<Window>,
+    texture_creator: &'a TextureCreator<WindowContext>,
+    theme: &mut Theme<'a>,
+) -> Result<(), Error> {
+    // Broken themes are expected while they're being edited, keep the last working one around
+    match Theme::new(texture_creator, app.theme_manifest_path()) {
+        Ok(new_theme) => {
+            *theme = new_theme;
+            app.theme_error = None;
+        }
+        Err(err) => {
+            let context = format!("Failed to load theme {}", app.theme_manifest_path().display());
+            app.show_error(&context, &err);
+            app.theme_error = Some(err.to_string());
+        }
+    }
+
+    canvas.window_mut().set_title(&app.window_title())?;
+
+    app.resize_window(canvas, theme)?;
+    app.render(canvas, theme)?;
+    Ok(())
+}
+
+fn switch_theme<'a>(
+    app: &mut AppState,
+    canvas: &mut Canvas<Window>,
+    texture_creator: &'a TextureCreator<WindowContext>,
+    theme: &mut Theme<'a>,
+    theme_watcher: &mut ThemeWatcher,
+) -> Result<(), Error> {
+    theme_watcher.watch(app.theme_manifest_path())?;
+    reload_theme(app, canvas, texture_creator, theme)
+}
+
+fn print_usage() {
+    eprintln!("Usage: aseqmatrix [--export-png <path> | --export-svg <path> | --panic [<port>]]");
+}
+
 fn main() -> Result<(), Error> {
+    let args = env::args().skip(1).collect::<Vec<String>>();
+    match args.iter().map(String::as_str).collect::<Vec<&str>>().as_slice() {
+        [] => {}
+        ["--export-png", path] | ["--export-svg", path] => {
+            let mut app = AppState::new()?;
+            app.refresh_midi_endpoints(&Seq::open(None, None, false)?)?;
+
+            if args[0] == "--export-png" {
+                export_png(&app, Path::new(path))?;
+            } else {
+                export_svg(&app, Path::new(path))?;
+            }
+
+            return Ok(());
+        }
+        ["--panic"] | ["--panic", _] => {
+            let seq = Seq::open(None, None, false)?;
+            let client_port = sequencer::create_client_port(&seq)?;
+
+            let mut app = AppState::new()?;
+            logging::init(&app.config.logging);
+            app.own_client = Some(seq.client_id()?);
+            app.refresh_midi_endpoints(&seq)?;
+
+            let output_addrs = match args.get(1) {
+                Some(port) => vec![app.find_port(port).ok_or(Error::GeneralError("unknown port"))?],
+                None => app.outputs.iter().map(|(output_addr, _)| *output_addr).collect(),
+            };
+
+            let status = sequencer::send_panic(&seq, &Mutex::new(app), client_port, &output_addrs);
+            if status.level == StatusLevel::Error {
+                return Err(Error::GeneralError("failed to send the panic to every port"));
+            }
+            println!("{}", status.text);
+
+            return Ok(());
+        }
+        _ => {
+            print_usage();
+            return Err(Error::GeneralError("invalid command line arguments"));
+        }
+    }
+
     let app = Arc::new(Mutex::new(AppState::new()?));
+    logging::init(&app.lock().unwrap().config.logging);
 
     let sdl_context = sdl2::init().map_err(sdl_error)?;
 
@@ -247,7 +1533,7 @@ fn main() -> Result<(), Error> {
     video_subsys.enable_screen_saver();
     sdl2::hint::set("SDL_MOUSE_FOCUS_CLICKTHROUGH", "1");
 
-    let window = video_subsys.window("ALSA Sequencer Matrix", 640, 480).hidden().build()?;
+    let window = video_subsys.window(WINDOW_TITLE, 640, 480).hidden().build()?;
 
     let mut canvas = window.into_canvas().build()?;
     let texture_creator = canvas.texture_creator();
@@ -259,236 +1545,435 @@ fn main() -> Result<(), Error> {
 
     {
         let mut app = app.lock().unwrap();
-        app.resize_window(&mut canvas, &theme)?;
-        app.render(&mut canvas, &theme)?;
+        app.resize_window(&mut canvas, &mut theme)?;
+        app.render(&mut canvas, &mut theme)?;
 
         // Window was created as hidden to avoid flickering during the initial resize
         canvas.window_mut().show();
     }
 
+    // Text input is only needed while a prompt is open
+    video_subsys.text_input().stop();
+
     let sdl_event = sdl_context.event().map_err(sdl_error)?;
     sdl_event.register_custom_event::<MidiPortChangeEvent>().map_err(sdl_error)?;
+    sdl_event.register_custom_event::<ThemeChangeEvent>().map_err(sdl_error)?;
+    sdl_event.register_custom_event::<ThemeWatchErrorEvent>().map_err(sdl_error)?;
+    sdl_event.register_custom_event::<MidiActivityEvent>().map_err(sdl_error)?;
+    sdl_event.register_custom_event::<SequencerReplyEvent>().map_err(sdl_error)?;
     sdl_event.push_custom_event(MidiPortChangeEvent).map_err(sdl_error)?;
     let tx = sdl_event.event_sender();
 
-    {
-        let app = Arc::clone(&app);
-        thread::spawn(move || -> Result<(), Error> {
-            let seq = Seq::open(None, None, false)?;
+    let mut theme_watcher = ThemeWatcher::new(sdl_event.event_sender())?;
+    theme_watcher.watch(&app.lock().unwrap().config.theme_manifest_path)?;
 
-            let midi_name = CString::new("ALSA Sequencer Matrix")?;
-            seq.set_client_name(&midi_name)?;
+    let sequencer = sequencer::spawn_sequencer(Arc::clone(&app), tx)?;
+    app.lock().unwrap().sequencer = Some(sequencer);
 
-            let client_port = {
-                let mut port_info = PortInfo::empty()?;
-                port_info.set_capability(PortCap::WRITE);
-                port_info.set_type(PortType::MIDI_GENERIC | PortType::APPLICATION);
-                port_info.set_name(&midi_name);
-                seq.create_port(&port_info)?;
-                port_info.addr()
-            };
+    // The patch bay works without the control socket, failing to open it is not fatal
+    let control_server = {
+        let mut app_state = app.lock().unwrap();
+        let control_config = app_state.config.control.clone();
 
-            {
-                let sub = PortSubscribe::empty()?;
-                sub.set_sender(Addr::system_announce());
-                sub.set_dest(client_port);
-                seq.subscribe_port(&sub)?;
+        if control_config.socket {
+            let socket_path = control_config.socket_path.map(Ok).unwrap_or_else(default_socket_path);
+            match socket_path.and_then(|socket_path| ControlServer::new(Arc::clone(&app), &socket_path)) {
+                Ok(control_server) => Some(control_server),
+                Err(err) => {
+                    app_state.show_error("Failed to open the control socket", &err);
+                    None
+                }
+            }
+        } else {
+            None
+        }
+    };
+
+    let osc_server = {
+        let mut app_state = app.lock().unwrap();
+        let control_config = app_state.config.control.clone();
+
+        if control_config.osc {
+            match OscServer::new(Arc::clone(&app), &control_config.osc_address) {
+                Ok(osc_server) => Some(osc_server),
+                Err(err) => {
+                    app_state.show_error("Failed to open the OSC port", &err);
+                    None
+                }
+            }
+        } else {
+            None
+        }
+    };
+
+    let mut events = sdl_context.event_pump().map_err(sdl_error)?;
+    'main: loop {
+        // Animations need a frame clock, otherwise there's nothing to redraw until the next event
+        let animation_timeout = app.lock().unwrap().animation_timeout(&theme);
+        let event = match animation_timeout {
+            Some(timeout) => events.wait_event_timeout(timeout.as_millis() as u32),
+            None => Some(events.wait_event()),
+        };
+
+        let event = match event {
+            Some(event) => event,
+            None => {
+                app.lock().unwrap().render(&mut canvas, &mut theme)?;
+                continue;
             }
+        };
+
+        if logging::enabled(LogLevel::Debug) {
+            logging::debug(&format!("SDL event: {:?}", event));
+        }
+
+        match event {
+            Event::Quit { .. } => {
+                break 'main;
+            }
+            Event::KeyDown { keycode: Some(Keycode::Escape), .. } => {
+                let mut app = app.lock().unwrap();
 
-            let refresh_midi_endpoints = || -> Result<(), Error> {
+                if app.prompt.take().is_some() {
+                    video_subsys.text_input().stop();
+                    app.resize_window(&mut canvas, &mut theme)?;
+                    app.render(&mut canvas, &mut theme)?;
+                } else if app.context_menu.take().is_some() {
+                    app.resize_window(&mut canvas, &mut theme)?;
+                    app.render(&mut canvas, &mut theme)?;
+                } else if app.theme_browser.take().is_some() {
+                    // The configured theme is loaded back
+                    switch_theme(&mut app, &mut canvas, &texture_creator, &mut theme, &mut theme_watcher)?;
+                } else if app.midi_learn.is_some() {
+                    if let Err(err) = app.send_command(SequencerCommand::LearnBinding(None)) {
+                        app.show_error("Failed to stop learning", &err);
+                    }
+                } else {
+                    break 'main;
+                }
+            }
+            Event::Window { win_event: WindowEvent::SizeChanged(_, _), .. } => {
+                // Workaround for SDL2 corrupting things right after resizing the window.
+                let app = app.lock().unwrap();
+                app.render(&mut canvas, &mut theme)?;
+            }
+            Event::MouseMotion { x, y, .. } => {
                 let mut app = app.lock().unwrap();
-                app.inputs.clear();
-                app.outputs.clear();
-                app.connections.clear();
-
-                for client in ClientIter::new(&seq) {
-                    for port in PortIter::new(&seq, client.get_client()) {
-                        if port.get_capability().contains(PortCap::SUBS_READ) {
-                            app.inputs.push((port.addr(), port.get_name()?.to_owned()));
+                app.mouse_last_position = Some(PixelPosition { x: x as isize, y: y as isize });
+
+                let menu_hover_changed = match &mut app.context_menu {
+                    Some(context_menu) => context_menu.hover(&theme, PixelPosition { x: x as isize, y: y as isize }),
+                    None => false,
+                };
+                if menu_hover_changed {
+                    app.render(&mut canvas, &mut theme)?;
+                }
+
+                app.update_selection(&mut canvas, &mut theme, PixelPosition { x: x as isize, y: y as isize }, false)?;
+
+                if let (Some(mut drag), Some(selection)) = (app.drag.take(), app.selection) {
+                    let paint = !drag.rubber_band && selection != drag.last_cell;
+                    if paint {
+                        let cells = drag.line_cells(selection);
+                        drag.last_cell = selection;
+                        if let Err(err) = app.paint_cells(&mut drag, &cells) {
+                            app.show_error("Failed to change the connection", &err);
                         }
+                    }
 
-                        if port.get_capability().contains(PortCap::SUBS_WRITE) {
-                            app.outputs.push((port.addr(), port.get_name()?.to_owned()));
+                    app.drag = Some(drag);
+                    if paint {
+                        app.resize_window(&mut canvas, &mut theme)?;
+                        app.render(&mut canvas, &mut theme)?;
+                    }
+                }
+            }
+            Event::MouseButtonDown { x, y, mouse_btn: MouseButton::Left, .. } => {
+                let mut app = app.lock().unwrap();
+                let theme_browser_position = app.theme_browser_position(&theme);
+
+                if let Some(context_menu) = app.context_menu.take() {
+                    let action =
+                        context_menu.action_under_position(&theme, PixelPosition { x: x as isize, y: y as isize });
+                    if let Some(action) = action {
+                        if let Err(err) = app.execute_menu_action(&context_menu, action) {
+                            app.show_error("Failed to execute the action", &err);
                         }
+                    }
+
+                    let content_dimension = app.layout.dimension;
+                    if let Some(context_menu) = &mut app.context_menu {
+                        context_menu.constrain(&theme, content_dimension);
+                    }
+                    if app.prompt.is_some() {
+                        video_subsys.text_input().start();
+                    }
+
+                    app.resize_window(&mut canvas, &mut theme)?;
+                    app.render(&mut canvas, &mut theme)?;
+                } else if app.prompt.is_some() {
+                    // The prompt is modal, it's closed with the keyboard
+                } else if let Some(toolbar_rect) = app
+                    .toolbar_rect(&theme)
+                    .filter(|toolbar_rect| toolbar_rect.contains(PixelPosition { x: x as isize, y: y as isize }))
+                {
+                    let panic_button_rect = toolbar::panic_button_rect(&theme, toolbar_rect);
 
-                        for sub in PortSubscribeIter::new(&seq, port.addr(), QuerySubsType::WRITE) {
-                            app.connections.push((sub.get_sender(), sub.get_dest()));
+                    if !panic_button_rect.contains(PixelPosition { x: x as isize, y: y as isize }) {
+                        app.status = None;
+                    } else if let Err(err) = app.send_panic(None) {
+                        app.show_error("Failed to send the panic", &err);
+                    }
+                    app.render(&mut canvas, &mut theme)?;
+                } else if let Some(theme_browser) = &mut app.theme_browser {
+                    let entry_index = theme_browser.entry_under_position(
+                        &theme,
+                        theme_browser_position,
+                        PixelPosition { x: x as isize, y: y as isize },
+                    );
+
+                    if let Some(entry_index) = entry_index {
+                        if theme_browser.select(entry_index) {
+                            switch_theme(&mut app, &mut canvas, &texture_creator, &mut theme, &mut theme_watcher)?;
                         }
                     }
+                } else if let Some(selection) = app.selection {
+                    app.drag = Some(DragState {
+                        anchor: selection,
+                        last_cell: selection,
+                        connect: !app.cell_connected(selection),
+                        painted: Vec::new(),
+                        rubber_band: sdl_context.keyboard().mod_state().intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD),
+                    });
+
+                    app.set_mouse_down(true);
+                    app.update_selection(
+                        &mut canvas,
+                        &mut theme,
+                        PixelPosition { x: x as isize, y: y as isize },
+                        true,
+                    )?;
                 }
+            }
+            Event::MouseButtonUp { x, y, mouse_btn: MouseButton::Left, .. } => {
+                let mut app = app.lock().unwrap();
 
-                Ok(())
-            };
-
-            refresh_midi_endpoints()?;
-
-            let mut fds = Vec::<alsa::poll::pollfd>::new();
-            fds.append(&mut (&seq, Some(alsa::Direction::Capture)).get()?);
-
-            let mut seq_input = seq.input();
-            loop {
-                if seq_input.event_input_pending(true)? > 0 {
-                    let event = seq_input.event_input()?;
-                    //println!("{:?}", event);
-                    // TODO: Filter events from this client
-                    // TODO: Why doesn't the system announcement port send any events
-                    //  about a port getting renamed under `EventType::PortChange`?
-                    match event.get_type() {
-                        alsa::seq::EventType::PortChange
-                        | alsa::seq::EventType::PortExit
-                        | alsa::seq::EventType::PortStart
-                        | alsa::seq::EventType::PortSubscribed
-                        | alsa::seq::EventType::PortUnsubscribed => {
-                            refresh_midi_endpoints()?;
-                            tx.push_custom_event(MidiPortChangeEvent).map_err(sdl_error)?;
+                if app.mouse_down {
+                    app.set_mouse_down(false);
+                    app.update_selection(
+                        &mut canvas,
+                        &mut theme,
+                        PixelPosition { x: x as isize, y: y as isize },
+                        true,
+                    )?;
+
+                    if let Some(mut drag) = app.drag.take() {
+                        let cells = if drag.rubber_band {
+                            app.selection.map(|selection| drag.rubber_band_cells(selection)).unwrap_or_default()
+                        } else if drag.painted.is_empty() && (app.selection == Some(drag.anchor)) {
+                            vec![drag.anchor]
+                        } else {
+                            Vec::new()
+                        };
+
+                        if let Err(err) = app.paint_cells(&mut drag, &cells) {
+                            app.show_error("Failed to change the connection", &err);
                         }
-                        _ => {}
+
+                        app.resize_window(&mut canvas, &mut theme)?;
+                        app.render(&mut canvas, &mut theme)?;
                     }
-                    continue;
                 }
-                alsa::poll::poll(&mut fds, -1)?;
             }
-        });
-    }
+            Event::MouseButtonDown { x, y, mouse_btn: MouseButton::Right, .. } => {
+                let mut app = app.lock().unwrap();
 
-    let mut events = sdl_context.event_pump().map_err(sdl_error)?;
-    'main: loop {
-        for event in events.wait_iter() {
-            //println!("{:?}", event);
-            match event {
-                Event::Quit { .. } | Event::KeyDown { keycode: Some(Keycode::Escape), .. } => {
-                    break 'main;
+                if app.theme_browser.is_none() && app.prompt.is_none() && !app.mouse_down {
+                    app.context_menu = app.context_menu_at(PixelPosition { x: x as isize, y: y as isize });
+
+                    let content_dimension = app.layout.dimension;
+                    if let Some(context_menu) = &mut app.context_menu {
+                        context_menu.constrain(&theme, content_dimension);
+                        app.selection = None;
+                    }
+
+                    app.resize_window(&mut canvas, &mut theme)?;
+                    app.render(&mut canvas, &mut theme)?;
                 }
-                Event::Window { win_event: WindowEvent::SizeChanged(_, _), .. } => {
-                    // Workaround for SDL2 corrupting things right after resizing the window.
-                    let app = app.lock().unwrap();
-                    app.render(&mut canvas, &theme)?;
+            }
+            Event::TextInput { text, .. } => {
+                let mut app = app.lock().unwrap();
+
+                if let Some(prompt) = &mut app.prompt {
+                    prompt.text.push_str(&text);
+                    app.resize_window(&mut canvas, &mut theme)?;
+                    app.render(&mut canvas, &mut theme)?;
                 }
-                Event::MouseMotion { x, y, .. } => {
-                    let mut app = app.lock().unwrap();
-                    app.mouse_last_position = Some(PixelPosition { x: x as isize, y: y as isize });
+            }
+            Event::KeyDown { keycode: Some(Keycode::Backspace), .. } => {
+                let mut app = app.lock().unwrap();
 
-                    app.update_selection(&mut canvas, &theme, PixelPosition { x: x as isize, y: y as isize }, false)?;
+                if let Some(prompt) = &mut app.prompt {
+                    prompt.text.pop();
+                    app.render(&mut canvas, &mut theme)?;
                 }
-                Event::MouseButtonDown { x, y, mouse_btn: MouseButton::Left, .. } => {
-                    let mut app = app.lock().unwrap();
+            }
+            Event::KeyDown { keycode: Some(Keycode::Return | Keycode::KpEnter), .. }
+                if app.lock().unwrap().prompt.is_some() =>
+            {
+                let mut app = app.lock().unwrap();
+                if let Err(err) = app.commit_prompt() {
+                    app.show_error("Failed to save the alias", &err);
+                }
+                video_subsys.text_input().stop();
 
-                    if app.selection.is_some() {
-                        app.mouse_down = true;
-                        app.update_selection(
-                            &mut canvas,
-                            &theme,
-                            PixelPosition { x: x as isize, y: y as isize },
-                            true,
-                        )?;
+                app.resize_window(&mut canvas, &mut theme)?;
+                app.render(&mut canvas, &mut theme)?;
+            }
+            // The prompt is modal, the function key shortcuts are ignored while it's open
+            Event::KeyDown {
+                keycode: Some(Keycode::F5 | Keycode::F8 | Keycode::F9 | Keycode::F10 | Keycode::F11 | Keycode::F12),
+                ..
+            } if app.lock().unwrap().prompt.is_some() => {}
+            event @ Event::User { .. } => {
+                let mut app = app.lock().unwrap();
+
+                if event.as_user_event_type::<MidiPortChangeEvent>().is_some() {
+                    if let Some(control_server) = &control_server {
+                        control_server.notify_change(&app);
                     }
-                }
-                Event::MouseButtonUp { x, y, mouse_btn: MouseButton::Left, .. } => {
-                    let mut app = app.lock().unwrap();
-
-                    if app.mouse_down {
-                        app.mouse_down = false;
-                        app.update_selection(
-                            &mut canvas,
-                            &theme,
-                            PixelPosition { x: x as isize, y: y as isize },
-                            true,
-                        )?;
-
-                        if let Some((selection_x, selection_y)) = app.selection {
-                            // assert!(selection in bounds)
-                            let new_input = app.inputs[selection_x].0;
-                            let new_output = app.outputs[selection_y].0;
-
-                            if new_input != new_output {
-                                let seq = Seq::open(None, None, false)?;
-                                if app.connections.contains(&(new_input, new_output)) {
-                                    seq.unsubscribe_port(new_input, new_output)?;
-                                } else {
-                                    // <feedback_loop_resolver>
-                                    let outgoing_target_ports = app
-                                        .connections
-                                        .iter()
-                                        .filter(|(input, _)| *input == new_output)
-                                        .map(|(_, output)| *output)
-                                        .collect::<Vec<Addr>>();
-
-                                    for &port in &outgoing_target_ports {
-                                        let mut todo = vec![port];
-                                        let mut done = vec![];
-                                        let mut feedback_loop_found = false;
-
-                                        while !todo.is_empty() {
-                                            let current = todo.pop().unwrap(); // Cannot fail because `!is_empty()`
-                                            if current == new_input {
-                                                feedback_loop_found = true;
-                                                break;
-                                            }
-                                            todo.extend_from_slice(
-                                                &app.connections
-                                                    .iter()
-                                                    .filter(|(input, _)| *input == current)
-                                                    .filter(|(_, output)| !done.contains(output))
-                                                    .map(|(_, output)| *output)
-                                                    .collect::<Vec<Addr>>(),
-                                            );
-                                            done.push(current);
-                                        }
-
-                                        if feedback_loop_found {
-                                            seq.unsubscribe_port(new_output, port)?;
-                                        }
-                                    }
-                                    // </feedback_loop_resolver>
-
-                                    let sub = PortSubscribe::empty()?;
-                                    sub.set_sender(new_input);
-                                    sub.set_dest(new_output);
-                                    seq.subscribe_port(&sub)?;
-                                }
-                            }
-                        }
+                    if let Some(osc_server) = &osc_server {
+                        osc_server.notify_change(&app);
+                    }
+
+                    canvas.window_mut().set_title(&app.window_title())?;
+                    app.resize_window(&mut canvas, &mut theme)?;
+                    app.render(&mut canvas, &mut theme)?;
+                } else if event.as_user_event_type::<ThemeChangeEvent>().is_some() {
+                    reload_theme(&mut app, &mut canvas, &texture_creator, &mut theme)?;
+                } else if let Some(ThemeWatchErrorEvent(status)) = event.as_user_event_type::<ThemeWatchErrorEvent>() {
+                    app.status = Some(status);
+                    app.render(&mut canvas, &mut theme)?;
+                } else if event.as_user_event_type::<MidiActivityEvent>().is_some() {
+                    app.render(&mut canvas, &mut theme)?;
+                } else if let Some(SequencerReplyEvent(reply)) = event.as_user_event_type::<SequencerReplyEvent>() {
+                    app.apply_sequencer_reply(reply);
+
+                    if let Some(control_server) = &control_server {
+                        control_server.notify_change(&app);
+                    }
+                    if let Some(osc_server) = &osc_server {
+                        osc_server.notify_change(&app);
                     }
+
+                    let content_dimension = app.layout.dimension;
+                    if let Some(context_menu) = &mut app.context_menu {
+                        context_menu.constrain(&theme, content_dimension);
+                    }
+
+                    app.resize_window(&mut canvas, &mut theme)?;
+                    app.render(&mut canvas, &mut theme)?;
+                }
+            }
+            Event::KeyDown { keycode: Some(Keycode::F10), .. } => {
+                let mut app = app.lock().unwrap();
+                app.config.show_crosshair = !app.config.show_crosshair;
+                if let Err(err) = app.config.save() {
+                    app.show_error("Failed to save the config", &err);
                 }
-                Event::User { .. } => {
-                    // TODO: Check user_event kind
-                    let mut app = app.lock().unwrap();
-                    app.resize_window(&mut canvas, &theme)?;
-                    app.render(&mut canvas, &theme)?;
+                app.resize_window(&mut canvas, &mut theme)?;
+                app.render(&mut canvas, &mut theme)?;
+            }
+            Event::KeyDown { keycode: Some(Keycode::F11), .. } => {
+                let mut app = app.lock().unwrap();
+                app.config.show_addresses = !app.config.show_addresses;
+                if let Err(err) = app.config.save() {
+                    app.show_error("Failed to save the config", &err);
                 }
-                Event::KeyDown { keycode: Some(Keycode::F11), .. } => {
-                    let mut app = app.lock().unwrap();
-                    app.config.show_addresses = !app.config.show_addresses;
-                    app.config.save()?;
-                    app.resize_window(&mut canvas, &theme)?;
-                    app.render(&mut canvas, &theme)?;
+                app.resize_window(&mut canvas, &mut theme)?;
+                app.render(&mut canvas, &mut theme)?;
+            }
+            Event::KeyDown { keycode: Some(Keycode::F12), .. }
+            | Event::KeyDown { keycode: Some(Keycode::Return), .. }
+            | Event::KeyDown { keycode: Some(Keycode::KpEnter), .. } => {
+                let mut app = app.lock().unwrap();
+
+                if let Some(theme_browser) = app.theme_browser.take() {
+                    app.config.theme_manifest_path = theme_browser.previewed_manifest_path().to_owned();
+                    if let Err(err) = app.config.save() {
+                        app.show_error("Failed to save the config", &err);
+                    }
+                } else if matches!(event, Event::KeyDown { keycode: Some(Keycode::F12), .. }) {
+                    match ThemeBrowser::new(&app.config.theme_manifest_path) {
+                        Ok(theme_browser) => app.theme_browser = theme_browser,
+                        Err(err) => app.show_error("Failed to open the theme browser", &err),
+                    }
+                    app.selection = None;
+                } else {
+                    // Enter only applies the theme browser
+                    continue;
                 }
-                Event::KeyDown { keycode: Some(Keycode::F12), .. } => {
-                    let mut app = app.lock().unwrap();
 
-                    let theme_manifest_paths = Theme::theme_manifest_paths()?;
+                app.resize_window(&mut canvas, &mut theme)?;
+                app.render(&mut canvas, &mut theme)?;
+            }
+            Event::KeyDown { keycode: Some(keycode @ (Keycode::Up | Keycode::Down)), .. } => {
+                let mut app = app.lock().unwrap();
+
+                if let Some(theme_browser) = &mut app.theme_browser {
+                    let selection_changed = match keycode {
+                        Keycode::Up => theme_browser.select_previous(),
+                        _ => theme_browser.select_next(),
+                    };
 
-                    let next_manifest_index = theme_manifest_paths
-                        .iter()
-                        .position(|manifest_path| manifest_path == &app.config.theme_manifest_path)
-                        .map(|manifest_index| (manifest_index + 1) % theme_manifest_paths.len())
-                        .unwrap_or(0);
+                    if selection_changed {
+                        switch_theme(&mut app, &mut canvas, &texture_creator, &mut theme, &mut theme_watcher)?;
+                    }
+                }
+            }
+            Event::KeyDown { keycode: Some(Keycode::F9), keymod, .. } => {
+                let mut app = app.lock().unwrap();
 
-                    app.config.theme_manifest_path = theme_manifest_paths[next_manifest_index].clone();
-                    app.config.save()?;
+                let export_result = if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) {
+                    default_export_path("svg").and_then(|path| export_svg(&app, &path).map(|_| path))
+                } else {
+                    default_export_path("png").and_then(|path| export_png(&app, &path).map(|_| path))
+                };
 
-                    theme = Theme::new(&texture_creator, &app.config.theme_manifest_path)?;
-                    app.resize_window(&mut canvas, &theme)?;
-                    app.render(&mut canvas, &theme)?;
+                match export_result {
+                    Ok(path) => {
+                        app.status = Some(StatusMessage::info(&format!("Exported the matrix to {}", path.display())));
+                    }
+                    Err(err) => app.show_error("Failed to export the matrix", &err),
                 }
-                Event::KeyDown { keycode: Some(Keycode::F5), .. } => {
-                    let mut app = app.lock().unwrap();
-                    theme = Theme::new(&texture_creator, &app.config.theme_manifest_path)?;
-                    app.resize_window(&mut canvas, &theme)?;
-                    app.render(&mut canvas, &theme)?;
+
+                app.resize_window(&mut canvas, &mut theme)?;
+                app.render(&mut canvas, &mut theme)?;
+            }
+            Event::KeyDown { keycode: Some(Keycode::F8), keymod, .. } => {
+                let mut app = app.lock().unwrap();
+
+                // With Shift, only the output port of the hovered row receives it
+                let panic_result = if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) {
+                    match app.selection.and_then(|(_, output_index)| app.outputs.get(output_index)) {
+                        Some(&(output_addr, _)) => app.send_panic(Some(output_addr)),
+                        None => Err(Error::GeneralError("no output port is selected")),
+                    }
+                } else {
+                    app.send_panic(None)
+                };
+
+                if let Err(err) = panic_result {
+                    app.show_error("Failed to send the panic", &err);
+                    app.resize_window(&mut canvas, &mut theme)?;
+                    app.render(&mut canvas, &mut theme)?;
                 }
-                _ => {}
             }
+            Event::KeyDown { keycode: Some(Keycode::F5), .. } => {
+                let mut app = app.lock().unwrap();
+                reload_theme(&mut app, &mut canvas, &texture_creator, &mut theme)?;
+            }
+            _ => {}
         }
     }
 
@@ -0,0 +1,183 @@
+use alsa::seq::Addr;
+use sdl2::rect::Rect;
+use sdl2::render::{Canvas, RenderTarget};
+
+use crate::config::SortMode;
+use crate::error::Error;
+use crate::graphics::{
+    draw_colored_string, draw_frame, draw_tiled_background, fill_rect, PixelDimension, PixelPosition,
+};
+use crate::theme::{Theme, ThemeColor};
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum MenuTarget {
+    Input(Addr),
+    Output(Addr),
+    Connection(Addr, Addr),
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum MenuAction {
+    DisconnectAll,
+    ConnectToAll,
+    HidePort,
+    ShowHiddenPorts,
+    RenameAlias,
+    Monitor,
+    ToggleExclusive,
+    EditAttributes,
+    ToggleTimeUpdate,
+    ToggleTimeReal,
+    ShowRoutePath,
+    Pin,
+    MoveUp,
+    MoveDown,
+    SortMenu,
+    SortBy(SortMode),
+    BindingsMenu,
+    // Indexes into the sorted snapshot names
+    LearnSnapshot(usize),
+    LearnNextSnapshot,
+    LearnPreviousSnapshot,
+    LearnToggle,
+    ClearBindings,
+    Panic,
+}
+
+// Items without an action are either disabled or purely informational
+pub struct MenuItem {
+    pub caption: String,
+    pub action: Option<MenuAction>,
+    pub dimmed: bool,
+}
+
+impl MenuItem {
+    pub fn new(caption: &str, action: MenuAction) -> MenuItem {
+        MenuItem { caption: caption.to_owned(), action: Some(action), dimmed: false }
+    }
+
+    pub fn disabled(caption: &str) -> MenuItem {
+        MenuItem { caption: caption.to_owned(), action: None, dimmed: true }
+    }
+
+    pub fn info(caption: &str) -> MenuItem {
+        MenuItem { caption: caption.to_owned(), action: None, dimmed: false }
+    }
+}
+
+pub struct ContextMenu {
+    pub target: MenuTarget,
+    items: Vec<MenuItem>,
+    position: PixelPosition,
+    hovered: Option<usize>,
+}
+
+impl ContextMenu {
+    pub fn new(target: MenuTarget, items: Vec<MenuItem>, position: PixelPosition) -> ContextMenu {
+        ContextMenu { target, items, position, hovered: None }
+    }
+
+    pub fn position(&self) -> PixelPosition {
+        self.position
+    }
+
+    pub fn dimension(&self, theme: &Theme) -> PixelDimension {
+        let padding = theme.borders_texture.tile_size;
+
+        PixelDimension {
+            width: self.items.iter().map(|item| item.caption.chars().count()).max().unwrap_or(0)
+                * theme.font_texture.tile_size.width
+                + 2 * padding.width,
+            height: self.items.len() * theme.font_texture.tile_size.height + 2 * padding.height,
+        }
+    }
+
+    // Keeps the menu inside the given area, menus opened near the edges would be cut off otherwise
+    pub fn constrain(&mut self, theme: &Theme, area: PixelDimension) {
+        let dimension = self.dimension(theme);
+
+        self.position = PixelPosition {
+            x: self.position.x.min(area.width as isize - dimension.width as isize).max(0),
+            y: self.position.y.min(area.height as isize - dimension.height as isize).max(0),
+        };
+    }
+
+    fn item_under_position(&self, theme: &Theme, position: PixelPosition) -> Option<usize> {
+        let (px, py) = (
+            position.x - self.position.x - theme.borders_texture.tile_size.width as isize,
+            position.y - self.position.y - theme.borders_texture.tile_size.height as isize,
+        );
+
+        if (px < 0)
+            || (py < 0)
+            || (px as usize >= self.dimension(theme).width - 2 * theme.borders_texture.tile_size.width)
+        {
+            return None;
+        }
+
+        let item_index = py as usize / theme.font_texture.tile_size.height;
+        if item_index < self.items.len() {
+            Some(item_index)
+        } else {
+            None
+        }
+    }
+
+    pub fn hover(&mut self, theme: &Theme, position: PixelPosition) -> bool {
+        let last_hovered = self.hovered;
+        self.hovered =
+            self.item_under_position(theme, position).filter(|&item_index| self.items[item_index].action.is_some());
+        self.hovered != last_hovered
+    }
+
+    pub fn action_under_position(&self, theme: &Theme, position: PixelPosition) -> Option<MenuAction> {
+        self.item_under_position(theme, position).and_then(|item_index| self.items[item_index].action)
+    }
+
+    pub fn render<T: RenderTarget>(&self, canvas: &mut Canvas<T>, theme: &mut Theme) -> Result<(), Error> {
+        let dimension = self.dimension(theme);
+
+        canvas.set_clip_rect(Some(Rect::new(
+            self.position.x as i32,
+            self.position.y as i32,
+            dimension.width as u32,
+            dimension.height as u32,
+        )));
+        draw_tiled_background(canvas, &theme.background_texture)?;
+        canvas.set_clip_rect(None);
+
+        draw_frame(canvas, &theme.borders_texture, self.position, dimension)?;
+
+        for (item_index, item) in self.items.iter().enumerate() {
+            let item_position = PixelPosition {
+                x: self.position.x + theme.borders_texture.tile_size.width as isize,
+                y: self.position.y
+                    + theme.borders_texture.tile_size.height as isize
+                    + item_index as isize * theme.font_texture.tile_size.height as isize,
+            };
+
+            let hovered = self.hovered == Some(item_index);
+            if hovered {
+                let ThemeColor(tint) = theme.manifest.crosshair.tint;
+                fill_rect(
+                    canvas,
+                    item_position,
+                    PixelDimension {
+                        width: dimension.width - 2 * theme.borders_texture.tile_size.width,
+                        height: theme.font_texture.tile_size.height,
+                    },
+                    tint,
+                )?;
+            }
+
+            let mut color = theme.manifest.label_colors.color(hovered, false);
+            if item.dimmed {
+                color.a /= 2;
+            }
+
+            draw_colored_string(canvas, &mut theme.font_texture, &item.caption, item_position, 0, color)?;
+        }
+
+        Ok(())
+    }
+}
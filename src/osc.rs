@@ -0,0 +1,412 @@
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crate::error::Error;
+use crate::logging;
+use crate::sequencer::SequencerCommand;
+use crate::AppState;
+
+// OSC packets are small, anything larger than this is dropped
+const MAX_PACKET_SIZE: usize = 8192;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum OscArgument {
+    Int(i32),
+    Float(f32),
+    String(String),
+    Blob(Vec<u8>),
+    Bool(bool),
+    Nil,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct OscMessage {
+    pub address: String,
+    pub arguments: Vec<OscArgument>,
+}
+
+fn pad_to_four(buffer: &mut Vec<u8>) {
+    while !buffer.len().is_multiple_of(4) {
+        buffer.push(0);
+    }
+}
+
+fn encode_string(buffer: &mut Vec<u8>, text: &str) {
+    buffer.extend_from_slice(text.as_bytes());
+    // Strings are always terminated, even when they're already aligned
+    buffer.push(0);
+    pad_to_four(buffer);
+}
+
+// Reads the packet from the front, every item is aligned to four bytes
+struct OscReader<'a> {
+    packet: &'a [u8],
+}
+
+impl<'a> OscReader<'a> {
+    fn take(&mut self, length: usize) -> Result<&'a [u8], Error> {
+        let padded_length = length.div_ceil(4) * 4;
+        if padded_length > self.packet.len() {
+            return Err(Error::GeneralError("truncated OSC packet"));
+        }
+
+        let (item, rest) = self.packet.split_at(padded_length);
+        self.packet = rest;
+        Ok(&item[..length])
+    }
+
+    fn read_string(&mut self) -> Result<String, Error> {
+        let length =
+            self.packet.iter().position(|&byte| byte == 0).ok_or(Error::GeneralError("unterminated OSC string"))?;
+        let bytes = self.take(length + 1)?;
+
+        String::from_utf8(bytes[..length].to_vec()).map_err(|_| Error::GeneralError("invalid UTF-8 in OSC string"))
+    }
+
+    fn read_u32(&mut self) -> Result<u32, Error> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+}
+
+impl OscMessage {
+    pub fn new(address: &str, arguments: Vec<OscArgument>) -> OscMessage {
+        OscMessage { address: address.to_owned(), arguments }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        encode_string(&mut buffer, &self.address);
+
+        let type_tags = self
+            .arguments
+            .iter()
+            .map(|argument| match argument {
+                OscArgument::Int(_) => 'i',
+                OscArgument::Float(_) => 'f',
+                OscArgument::String(_) => 's',
+                OscArgument::Blob(_) => 'b',
+                OscArgument::Bool(true) => 'T',
+                OscArgument::Bool(false) => 'F',
+                OscArgument::Nil => 'N',
+            })
+            .collect::<String>();
+        encode_string(&mut buffer, &format!(",{}", type_tags));
+
+        for argument in &self.arguments {
+            match argument {
+                OscArgument::Int(value) => buffer.extend_from_slice(&value.to_be_bytes()),
+                OscArgument::Float(value) => buffer.extend_from_slice(&value.to_be_bytes()),
+                OscArgument::String(text) => encode_string(&mut buffer, text),
+                OscArgument::Blob(bytes) => {
+                    buffer.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+                    buffer.extend_from_slice(bytes);
+                    pad_to_four(&mut buffer);
+                }
+                OscArgument::Bool(_) | OscArgument::Nil => {}
+            }
+        }
+
+        buffer
+    }
+
+    // Bundles are flattened into their messages, their time tags are ignored
+    pub fn decode(packet: &[u8]) -> Result<Vec<OscMessage>, Error> {
+        let mut reader = OscReader { packet };
+
+        if packet.starts_with(b"#bundle\0") {
+            reader.take(8)?;
+            reader.take(8)?; // Time tag
+
+            let mut messages = Vec::new();
+            while !reader.packet.is_empty() {
+                let element_length = reader.read_u32()? as usize;
+                if !element_length.is_multiple_of(4) {
+                    return Err(Error::GeneralError("misaligned OSC bundle element"));
+                }
+                messages.extend(OscMessage::decode(reader.take(element_length)?)?);
+            }
+            return Ok(messages);
+        }
+
+        let address = reader.read_string()?;
+        if !address.starts_with('/') {
+            return Err(Error::GeneralError("invalid OSC address"));
+        }
+
+        // The type tag string is optional in old implementations, it means no arguments then
+        let type_tags = if reader.packet.is_empty() { ",".to_owned() } else { reader.read_string()? };
+        let type_tags = type_tags.strip_prefix(',').ok_or(Error::GeneralError("invalid OSC type tags"))?;
+
+        let mut arguments = Vec::new();
+        for type_tag in type_tags.chars() {
+            arguments.push(match type_tag {
+                'i' => OscArgument::Int(reader.read_u32()? as i32),
+                'f' => OscArgument::Float(f32::from_bits(reader.read_u32()?)),
+                's' => OscArgument::String(reader.read_string()?),
+                'b' => {
+                    let length = reader.read_u32()? as usize;
+                    OscArgument::Blob(reader.take(length)?.to_vec())
+                }
+                'T' => OscArgument::Bool(true),
+                'F' => OscArgument::Bool(false),
+                'N' => OscArgument::Nil,
+                _ => return Err(Error::GeneralError("unsupported OSC argument type")),
+            });
+        }
+
+        Ok(vec![OscMessage { address, arguments }])
+    }
+
+    fn string_argument(&self, index: usize) -> Result<&str, Error> {
+        match self.arguments.get(index) {
+            Some(OscArgument::String(text)) => Ok(text),
+            _ => Err(Error::GeneralError("missing string argument")),
+        }
+    }
+}
+
+// Registered clients get notified about every change, like the subscribers of the control socket.
+// The notifications are sent from a separate thread too.
+pub struct OscServer {
+    socket: UdpSocket,
+    notification_sender: Sender<Vec<u8>>,
+}
+
+impl OscServer {
+    pub fn new(app: Arc<Mutex<AppState>>, address: &str) -> Result<OscServer, Error> {
+        let socket = UdpSocket::bind(address)?;
+        let clients = Arc::new(Mutex::new(Vec::new()));
+
+        {
+            let socket = socket.try_clone()?;
+            let clients = Arc::clone(&clients);
+
+            thread::spawn(move || {
+                let mut packet = [0; MAX_PACKET_SIZE];
+
+                while let Ok((length, source)) = socket.recv_from(&mut packet) {
+                    let result = OscMessage::decode(&packet[..length]).and_then(|messages| {
+                        messages.iter().try_for_each(|message| handle_message(&app, message, source, &clients))
+                    });
+
+                    if let Err(err) = result {
+                        logging::error(&format!("Failed to handle OSC packet from {}: {}", source, err));
+                        let reply = OscMessage::new("/aseqmatrix/error", vec![OscArgument::String(err.to_string())]);
+                        let _ = socket.send_to(&reply.encode(), source);
+                    }
+                }
+            });
+        }
+
+        let (notification_sender, notification_receiver) = mpsc::channel();
+        {
+            let socket = socket.try_clone()?;
+            thread::spawn(move || send_notifications(notification_receiver, &socket, &clients));
+        }
+
+        let osc_server = OscServer { socket, notification_sender };
+        logging::info(&format!("OSC server listening on {}", osc_server.local_addr()?));
+        Ok(osc_server)
+    }
+
+    // Differs from the configured address when it was bound to port 0
+    pub fn local_addr(&self) -> Result<SocketAddr, Error> {
+        Ok(self.socket.local_addr()?)
+    }
+
+    // Sent as `/aseqmatrix/connections` with a sender and a destination string per connection
+    pub fn notify_change(&self, app: &AppState) {
+        let arguments = app
+            .connections
+            .iter()
+            .flat_map(|&(sender, dest)| [sender, dest])
+            .map(|port_addr| OscArgument::String(format!("{}:{}", port_addr.client, port_addr.port)))
+            .collect();
+        let notification = OscMessage::new("/aseqmatrix/connections", arguments).encode();
+
+        let _ = self.notification_sender.send(notification);
+    }
+}
+
+// Like on the control socket, only the latest notification of a burst is sent
+fn send_notifications(notification_receiver: Receiver<Vec<u8>>, socket: &UdpSocket, clients: &Mutex<Vec<SocketAddr>>) {
+    while let Ok(notification) = notification_receiver.recv() {
+        let notification = notification_receiver.try_iter().last().unwrap_or(notification);
+        for client in clients.lock().unwrap().iter() {
+            let _ = socket.send_to(&notification, client);
+        }
+    }
+}
+
+fn handle_message(
+    app: &Mutex<AppState>,
+    message: &OscMessage,
+    source: SocketAddr,
+    clients: &Mutex<Vec<SocketAddr>>,
+) -> Result<(), Error> {
+    logging::debug(&format!("OSC message from {}: {:?}", source, message));
+
+    match message.address.as_str() {
+        "/aseqmatrix/connect" | "/aseqmatrix/disconnect" => {
+            let app = app.lock().unwrap();
+            let port = |index| {
+                message
+                    .string_argument(index)
+                    .and_then(|port| app.find_port(port).ok_or(Error::GeneralError("unknown port")))
+            };
+            let connection = (port(0)?, port(1)?);
+
+            if message.address == "/aseqmatrix/connect" {
+                app.send_command(SequencerCommand::Connect(vec![connection]))
+            } else {
+                app.send_command(SequencerCommand::Disconnect(vec![connection]))
+            }
+        }
+        "/aseqmatrix/snapshot/load" => app.lock().unwrap().load_snapshot(message.string_argument(0)?),
+        "/aseqmatrix/snapshot/save" => app.lock().unwrap().save_snapshot(message.string_argument(0)?),
+        "/aseqmatrix/register" | "/aseqmatrix/unregister" => {
+            let client = notification_address(message, source)?;
+
+            let mut clients = clients.lock().unwrap();
+            clients.retain(|&registered_client| registered_client != client);
+            if message.address == "/aseqmatrix/register" {
+                clients.push(client);
+            }
+            Ok(())
+        }
+        _ => Err(Error::GeneralError("unknown OSC address")),
+    }
+}
+
+// Notifications go to the source of the registration. Local clients may name another port
+// instead, a remote sender could otherwise point the notifications at any port of its host.
+fn notification_address(message: &OscMessage, source: SocketAddr) -> Result<SocketAddr, Error> {
+    match message.arguments.first() {
+        Some(&OscArgument::Int(_)) if !source.ip().is_loopback() => {
+            Err(Error::GeneralError("the notification port can only be given by local clients"))
+        }
+        Some(&OscArgument::Int(port)) => {
+            Ok(SocketAddr::new(source.ip(), u16::try_from(port).map_err(|_| Error::GeneralError("invalid port"))?))
+        }
+        _ => Ok(source),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alsa::seq::Addr;
+
+    #[test]
+    fn messages_are_encoded_with_padding() {
+        let message = OscMessage::new(
+            "/aseqmatrix/connect",
+            vec![OscArgument::String("20:0".to_owned()), OscArgument::Int(-2), OscArgument::Bool(true)],
+        );
+
+        let mut expected = Vec::new();
+        expected.extend_from_slice(b"/aseqmatrix/connect\0");
+        expected.extend_from_slice(b",siT\0\0\0\0");
+        expected.extend_from_slice(b"20:0\0\0\0\0");
+        expected.extend_from_slice(&[0xff, 0xff, 0xff, 0xfe]);
+
+        assert_eq!(message.encode(), expected);
+    }
+
+    #[test]
+    fn encoded_messages_decode_to_themselves() {
+        let message = OscMessage::new(
+            "/aseqmatrix/snapshot/load",
+            vec![
+                OscArgument::String("Verse".to_owned()),
+                OscArgument::Float(0.5),
+                OscArgument::Blob(vec![1, 2, 3, 4, 5]),
+                OscArgument::Nil,
+                OscArgument::Int(7),
+            ],
+        );
+
+        assert_eq!(OscMessage::decode(&message.encode()).unwrap(), vec![message]);
+    }
+
+    #[test]
+    fn bundles_are_flattened() {
+        let first = OscMessage::new("/aseqmatrix/register", vec![]);
+        let second = OscMessage::new("/aseqmatrix/disconnect", vec![OscArgument::String("a".to_owned())]);
+
+        let mut packet = b"#bundle\0".to_vec();
+        packet.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 1]);
+        for message in [&first, &second] {
+            let encoded = message.encode();
+            packet.extend_from_slice(&(encoded.len() as u32).to_be_bytes());
+            packet.extend_from_slice(&encoded);
+        }
+
+        assert_eq!(OscMessage::decode(&packet).unwrap(), vec![first, second]);
+    }
+
+    #[test]
+    fn malformed_packets_are_rejected() {
+        let valid = OscMessage::new("/aseqmatrix/connect", vec![OscArgument::Int(1)]).encode();
+
+        assert!(OscMessage::decode(&valid[..valid.len() - 1]).is_err());
+        assert!(OscMessage::decode(b"aseqmatrix\0\0").is_err());
+        assert!(OscMessage::decode(b"/a\0\0,x\0\0").is_err());
+        assert!(OscMessage::decode(b"/aseqmatrix").is_err());
+        assert!(OscMessage::decode(b"#bundle\0\0\0\0\0\0\0\0\0\0\0\0\x09").is_err());
+    }
+
+    #[test]
+    fn notification_ports_are_only_overridden_locally() {
+        let register = OscMessage::new("/aseqmatrix/register", vec![OscArgument::Int(9001)]);
+        let local_source = "127.0.0.1:50000".parse().unwrap();
+        let remote_source = "192.168.1.2:50000".parse().unwrap();
+
+        assert_eq!(notification_address(&register, local_source).unwrap(), "127.0.0.1:9001".parse().unwrap());
+        assert!(notification_address(&register, remote_source).is_err());
+
+        let register = OscMessage::new("/aseqmatrix/register", vec![]);
+        assert_eq!(notification_address(&register, remote_source).unwrap(), remote_source);
+    }
+
+    #[test]
+    fn messages_are_handled_over_udp() {
+        let app = Arc::new(Mutex::new(AppState::with_config(Default::default())));
+        let osc_server = OscServer::new(Arc::clone(&app), "127.0.0.1:0").unwrap();
+
+        let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+        client.set_read_timeout(Some(std::time::Duration::from_secs(5))).unwrap();
+        client.connect(osc_server.local_addr().unwrap()).unwrap();
+
+        let mut packet = [0; MAX_PACKET_SIZE];
+        let mut receive = || {
+            let length = client.recv(&mut packet).unwrap();
+            OscMessage::decode(&packet[..length]).unwrap()
+        };
+
+        // Packets are handled in order, the reply to the second one means the first one was handled too
+        client.send(&OscMessage::new("/aseqmatrix/register", vec![]).encode()).unwrap();
+        client.send(&OscMessage::new("/aseqmatrix/unknown", vec![]).encode()).unwrap();
+        assert_eq!(
+            receive(),
+            vec![OscMessage::new(
+                "/aseqmatrix/error",
+                vec![OscArgument::String("General error: unknown OSC address".to_owned())]
+            )]
+        );
+
+        let mut app = app.lock().unwrap();
+        app.connections = vec![(Addr { client: 20, port: 0 }, Addr { client: 128, port: 1 })];
+        osc_server.notify_change(&app);
+        assert_eq!(
+            receive(),
+            vec![OscMessage::new(
+                "/aseqmatrix/connections",
+                vec![OscArgument::String("20:0".to_owned()), OscArgument::String("128:1".to_owned())]
+            )]
+        );
+    }
+}
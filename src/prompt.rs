@@ -0,0 +1,72 @@
+use alsa::seq::Addr;
+use sdl2::rect::Rect;
+use sdl2::render::{Canvas, RenderTarget};
+
+use crate::error::Error;
+use crate::graphics::{draw_colored_string, draw_frame, draw_tiled_background, PixelDimension, PixelPosition};
+use crate::theme::Theme;
+
+pub struct TextPrompt {
+    pub target: Addr,
+    pub text: String,
+    caption: String,
+    position: PixelPosition,
+}
+
+impl TextPrompt {
+    const MINIMUM_TEXT_LENGTH: usize = 24;
+
+    pub fn new(target: Addr, caption: &str, text: &str, position: PixelPosition) -> TextPrompt {
+        TextPrompt { target, text: text.to_owned(), caption: caption.to_owned(), position }
+    }
+
+    pub fn position(&self) -> PixelPosition {
+        self.position
+    }
+
+    fn lines(&self) -> [String; 4] {
+        [self.caption.clone(), format!("{}_", self.text), String::new(), "Enter: Apply  Escape: Cancel".to_owned()]
+    }
+
+    pub fn dimension(&self, theme: &Theme) -> PixelDimension {
+        let lines = self.lines();
+        let padding = theme.borders_texture.tile_size;
+
+        PixelDimension {
+            width: lines.iter().map(|line| line.chars().count()).max().unwrap_or(0).max(Self::MINIMUM_TEXT_LENGTH)
+                * theme.font_texture.tile_size.width
+                + 2 * padding.width,
+            height: lines.len() * theme.font_texture.tile_size.height + 2 * padding.height,
+        }
+    }
+
+    pub fn render<T: RenderTarget>(&self, canvas: &mut Canvas<T>, theme: &mut Theme) -> Result<(), Error> {
+        let dimension = self.dimension(theme);
+
+        canvas.set_clip_rect(Some(Rect::new(
+            self.position.x as i32,
+            self.position.y as i32,
+            dimension.width as u32,
+            dimension.height as u32,
+        )));
+        draw_tiled_background(canvas, &theme.background_texture)?;
+        canvas.set_clip_rect(None);
+
+        draw_frame(canvas, &theme.borders_texture, self.position, dimension)?;
+
+        for (line_index, line) in self.lines().iter().enumerate() {
+            let text_position = PixelPosition {
+                x: self.position.x + theme.borders_texture.tile_size.width as isize,
+                y: self.position.y
+                    + theme.borders_texture.tile_size.height as isize
+                    + line_index as isize * theme.font_texture.tile_size.height as isize,
+            };
+
+            // The edited text stands out from the rest of the lines
+            let color = theme.manifest.label_colors.color(line_index == 1, false);
+            draw_colored_string(canvas, &mut theme.font_texture, line, text_position, 0, color)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,272 @@
+use alsa::seq::{Addr, EvCtrl, Event, EventType, PortSubscribe, PortSubscribeIter, QuerySubsType, Seq};
+
+use crate::error::Error;
+use crate::logging;
+
+// Looked up from the sequencer, the routing changes are logged with the names that were
+// current at the time
+fn port_caption(seq: &Seq, port: Addr) -> String {
+    let client_name =
+        seq.get_any_client_info(port.client).ok().and_then(|client| client.get_name().ok().map(str::to_owned));
+    let port_name = seq.get_any_port_info(port).ok().and_then(|port_info| port_info.get_name().ok().map(str::to_owned));
+
+    format!("{}:{} ({}:{})", client_name.unwrap_or_default(), port_name.unwrap_or_default(), port.client, port.port)
+}
+
+fn log_routing_change(seq: &Seq, action: &str, input: Addr, output: Addr) {
+    logging::info(&format!("{} {} -> {}", action, port_caption(seq, input), port_caption(seq, output)));
+}
+
+// Returns the ports `output` is already connected to which lead back to `input`,
+// subscribing `input` to `output` would close a feedback loop through each of them.
+fn feedback_loop_ports(connections: &[(Addr, Addr)], input: Addr, output: Addr) -> Vec<Addr> {
+    let outgoing_target_ports = connections
+        .iter()
+        .filter(|(connection_input, _)| *connection_input == output)
+        .map(|(_, connection_output)| *connection_output)
+        .collect::<Vec<Addr>>();
+
+    let mut feedback_loop_ports = Vec::new();
+
+    for &port in &outgoing_target_ports {
+        let mut todo = vec![port];
+        let mut done = vec![];
+
+        while let Some(current) = todo.pop() {
+            if current == input {
+                feedback_loop_ports.push(port);
+                break;
+            }
+            todo.extend(
+                connections
+                    .iter()
+                    .filter(|(connection_input, _)| *connection_input == current)
+                    .filter(|(_, connection_output)| !done.contains(connection_output))
+                    .map(|(_, connection_output)| *connection_output),
+            );
+            done.push(current);
+        }
+    }
+
+    feedback_loop_ports
+}
+
+// `connections` is updated in place, so that consecutive changes see each other
+// before the sequencer thread gets to refresh the state. Returns the connections
+// removed for resolving feedback loops.
+pub fn connect(
+    seq: &Seq,
+    connections: &mut Vec<(Addr, Addr)>,
+    input: Addr,
+    output: Addr,
+) -> Result<Vec<(Addr, Addr)>, Error> {
+    let mut removed_connections = Vec::new();
+
+    for port in feedback_loop_ports(connections, input, output) {
+        log_routing_change(seq, "Unsubscribing to break a feedback loop:", output, port);
+        seq.unsubscribe_port(output, port)?;
+        connections.retain(|&connection| connection != (output, port));
+        removed_connections.push((output, port));
+    }
+
+    log_routing_change(seq, "Subscribing", input, output);
+    let sub = PortSubscribe::empty()?;
+    sub.set_sender(input);
+    sub.set_dest(output);
+    seq.subscribe_port(&sub)?;
+    connections.push((input, output));
+
+    Ok(removed_connections)
+}
+
+pub fn disconnect(seq: &Seq, connections: &mut Vec<(Addr, Addr)>, input: Addr, output: Addr) -> Result<(), Error> {
+    log_routing_change(seq, "Unsubscribing", input, output);
+    seq.unsubscribe_port(input, output)?;
+    connections.retain(|&connection| connection != (input, output));
+
+    Ok(())
+}
+
+// Controller numbers of the channel messages sent to destinations
+const SUSTAIN_PEDAL: u32 = 64;
+const ALL_SOUND_OFF: u32 = 120;
+const RESET_ALL_CONTROLLERS: u32 = 121;
+const ALL_NOTES_OFF: u32 = 123;
+
+// The events are sent directly from our port, on every channel
+fn send_controllers(seq: &Seq, client_port: Addr, output: Addr, controllers: &[(u32, i32)]) -> Result<(), Error> {
+    for channel in 0..16 {
+        for &(param, value) in controllers {
+            let mut event = Event::new(EventType::Controller, &EvCtrl { channel, param, value });
+            event.set_source(client_port.port);
+            event.set_dest(output);
+            event.set_direct();
+            seq.event_output(&mut event)?;
+        }
+    }
+
+    seq.drain_output()?;
+    Ok(())
+}
+
+// Notes held by a sender which is about to be unsubscribed would never receive their note offs
+pub fn release_notes(seq: &Seq, client_port: Addr, output: Addr) -> Result<(), Error> {
+    logging::info(&format!("Releasing the notes of {}", port_caption(seq, output)));
+    send_controllers(seq, client_port, output, &[(SUSTAIN_PEDAL, 0), (ALL_NOTES_OFF, 0)])
+}
+
+pub fn send_panic(seq: &Seq, client_port: Addr, output: Addr) -> Result<(), Error> {
+    logging::info(&format!("Sending panic to {}", port_caption(seq, output)));
+    send_controllers(seq, client_port, output, &[(ALL_NOTES_OFF, 0), (ALL_SOUND_OFF, 0), (RESET_ALL_CONTROLLERS, 0)])
+}
+
+#[derive(Copy, Clone)]
+pub struct SubscriptionAttributes {
+    pub exclusive: bool,
+    pub time_update: bool,
+    pub time_real: bool,
+}
+
+#[derive(Copy, Clone)]
+pub enum SubscriptionAttribute {
+    Exclusive,
+    TimeUpdate,
+    TimeReal,
+}
+
+impl SubscriptionAttributes {
+    pub fn toggle(&mut self, attribute: SubscriptionAttribute) {
+        match attribute {
+            SubscriptionAttribute::Exclusive => self.exclusive = !self.exclusive,
+            SubscriptionAttribute::TimeUpdate => self.time_update = !self.time_update,
+            SubscriptionAttribute::TimeReal => self.time_real = !self.time_real,
+        }
+    }
+}
+
+pub fn subscription_attributes(seq: &Seq, input: Addr, output: Addr) -> Option<SubscriptionAttributes> {
+    PortSubscribeIter::new(seq, input, QuerySubsType::READ).find(|sub| sub.get_dest() == output).map(|sub| {
+        SubscriptionAttributes {
+            exclusive: sub.get_exclusive(),
+            time_update: sub.get_time_update(),
+            time_real: sub.get_time_real(),
+        }
+    })
+}
+
+fn subscribe(seq: &Seq, input: Addr, output: Addr, attributes: SubscriptionAttributes) -> Result<(), Error> {
+    let sub = PortSubscribe::empty()?;
+    sub.set_sender(input);
+    sub.set_dest(output);
+    sub.set_exclusive(attributes.exclusive);
+    sub.set_time_update(attributes.time_update);
+    sub.set_time_real(attributes.time_real);
+    seq.subscribe_port(&sub)?;
+
+    Ok(())
+}
+
+// Attributes of an existing subscription cannot be changed, it has to be recreated. When the
+// new attributes are refused, the subscription is restored with the previous ones.
+pub fn resubscribe(
+    seq: &Seq,
+    input: Addr,
+    output: Addr,
+    previous_attributes: SubscriptionAttributes,
+    attributes: SubscriptionAttributes,
+) -> Result<(), Error> {
+    let action = format!(
+        "Resubscribing with exclusive={} time-update={} time-real={}:",
+        attributes.exclusive, attributes.time_update, attributes.time_real
+    );
+    log_routing_change(seq, &action, input, output);
+    seq.unsubscribe_port(input, output)?;
+
+    if let Err(err) = subscribe(seq, input, output, attributes) {
+        log_routing_change(seq, "Restoring the previous attributes:", input, output);
+        if let Err(restore_err) = subscribe(seq, input, output, previous_attributes) {
+            logging::error(&format!("Failed to restore the subscription: {}", restore_err));
+        }
+        return Err(err);
+    }
+
+    Ok(())
+}
+
+// Shortest chain of ports events of `input` travel through before reaching `output`
+pub fn route_path(connections: &[(Addr, Addr)], input: Addr, output: Addr) -> Option<Vec<Addr>> {
+    let mut paths = vec![vec![input]];
+    let mut visited = vec![input];
+
+    while !paths.is_empty() {
+        let mut next_paths = Vec::new();
+
+        for path in paths {
+            let current = *path.last().unwrap(); // Cannot fail, paths are never empty
+
+            for &(_, connection_output) in
+                connections.iter().filter(|(connection_input, _)| *connection_input == current)
+            {
+                if visited.contains(&connection_output) {
+                    continue;
+                }
+                visited.push(connection_output);
+
+                let mut next_path = path.clone();
+                next_path.push(connection_output);
+
+                if connection_output == output {
+                    return Some(next_path);
+                }
+                next_paths.push(next_path);
+            }
+        }
+
+        paths = next_paths;
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn port(client: i32) -> Addr {
+        Addr { client, port: 0 }
+    }
+
+    #[test]
+    fn feedback_loops_are_detected() {
+        // 20 -> 128 -> 129 -> 130, and 129 -> 131
+        let connections =
+            [(port(20), port(128)), (port(128), port(129)), (port(129), port(130)), (port(129), port(131))];
+
+        // The port after `output` on each way back to `input` is reported
+        assert_eq!(feedback_loop_ports(&connections, port(129), port(128)), vec![port(129)]);
+        assert_eq!(feedback_loop_ports(&connections, port(130), port(20)), vec![port(128)]);
+        assert_eq!(feedback_loop_ports(&connections, port(130), port(128)), vec![port(129)]);
+        assert!(feedback_loop_ports(&connections, port(20), port(131)).is_empty());
+        assert!(feedback_loop_ports(&[], port(20), port(128)).is_empty());
+    }
+
+    #[test]
+    fn shortest_routes_are_found() {
+        let connections = [
+            (port(20), port(128)),
+            (port(128), port(129)),
+            (port(129), port(130)),
+            (port(20), port(131)),
+            (port(131), port(130)),
+            (port(130), port(20)),
+        ];
+
+        assert_eq!(route_path(&connections, port(20), port(130)), Some(vec![port(20), port(131), port(130)]));
+        assert_eq!(
+            route_path(&connections, port(128), port(131)),
+            Some(vec![port(128), port(129), port(130), port(20), port(131)])
+        );
+        assert_eq!(route_path(&connections, port(20), port(128)), Some(vec![port(20), port(128)]));
+        assert_eq!(route_path(&connections, port(20), port(132)), None);
+    }
+}
@@ -0,0 +1,694 @@
+use std::ffi::CString;
+use std::os::unix::io::RawFd;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use alsa::poll::Flags;
+use alsa::seq::{Addr, Connect, EventType, PortCap, PortInfo, PortSubscribe, PortType, Seq};
+use alsa::PollDescriptors;
+use nix::errno::Errno;
+use nix::fcntl::OFlag;
+use nix::unistd;
+use sdl2::event::EventSender;
+
+use crate::bindings::{BindingAction, MidiTrigger};
+use crate::config::LogLevel;
+use crate::endpoints::{query_port_keys, EndpointDelta, EndpointSnapshot, PortEntry};
+use crate::error::{sdl_error, Error};
+use crate::logging;
+use crate::routing::{self, SubscriptionAttribute, SubscriptionAttributes};
+use crate::snapshot::{Snapshot, SnapshotDiff};
+use crate::status::{StatusLevel, StatusMessage};
+use crate::{AppState, WINDOW_TITLE};
+
+pub struct MidiPortChangeEvent;
+
+pub struct MidiActivityEvent;
+
+// Every change to the sequencer is made through the long-lived client of the sequencer
+// thread. Opening a client per change would flood the announcements with client starts
+// and exits.
+pub enum SequencerCommand {
+    Connect(Vec<(Addr, Addr)>),
+    Disconnect(Vec<(Addr, Addr)>),
+    ToggleMonitor(Addr),
+    QueryAttributes(Addr, Addr),
+    ToggleAttribute(Addr, Addr, SubscriptionAttribute),
+    LoadSnapshot(String, Snapshot),
+    // `None` stops learning
+    LearnBinding(Option<BindingAction>),
+    Panic(Vec<Addr>),
+}
+
+impl SequencerCommand {
+    fn failure_caption(&self) -> &'static str {
+        match self {
+            SequencerCommand::Connect(_) => "Failed to connect",
+            SequencerCommand::Disconnect(_) => "Failed to disconnect",
+            SequencerCommand::ToggleMonitor(_) => "Failed to toggle monitoring",
+            SequencerCommand::QueryAttributes(_, _) => "Failed to read the subscription attributes",
+            SequencerCommand::ToggleAttribute(_, _, _) => "Failed to change the subscription attributes",
+            SequencerCommand::LoadSnapshot(_, _) => "Failed to load the snapshot",
+            SequencerCommand::LearnBinding(_) => "Failed to learn the MIDI binding",
+            SequencerCommand::Panic(_) => "Failed to send the panic",
+        }
+    }
+}
+
+pub enum SequencerReply {
+    Status(StatusMessage),
+    Attributes(Addr, Addr, SubscriptionAttributes),
+}
+
+pub struct SequencerReplyEvent(pub SequencerReply);
+
+// The steps of loading a snapshot which may fail independently of each other
+enum SnapshotFailure {
+    ReleaseNotes(Addr),
+    Disconnect(Addr, Addr),
+    Connect(Addr, Addr),
+}
+
+// Sent back to the callers waiting for a command, failures carry the text of the status message
+pub type CommandOutcome = Result<(), Error>;
+
+struct QueuedCommand {
+    command: SequencerCommand,
+    outcome_sender: Option<Sender<CommandOutcome>>,
+}
+
+// The commands are queued on a channel, the sequencer thread is woken up from its poll
+// through a pipe
+pub struct SequencerHandle {
+    commands: Sender<QueuedCommand>,
+    wake_fd: RawFd,
+}
+
+impl SequencerHandle {
+    pub fn send(&self, command: SequencerCommand) -> Result<(), Error> {
+        self.queue(QueuedCommand { command, outcome_sender: None })
+    }
+
+    // The outcome arrives on the returned receiver once the command has been carried out
+    pub fn send_with_outcome(&self, command: SequencerCommand) -> Result<Receiver<CommandOutcome>, Error> {
+        let (outcome_sender, outcome_receiver) = mpsc::channel();
+        self.queue(QueuedCommand { command, outcome_sender: Some(outcome_sender) })?;
+        Ok(outcome_receiver)
+    }
+
+    fn queue(&self, command: QueuedCommand) -> Result<(), Error> {
+        self.commands.send(command).map_err(|_| Error::GeneralError("sequencer thread is not running"))?;
+
+        // A full pipe already has a wakeup pending
+        match unistd::write(self.wake_fd, &[0]) {
+            Ok(_) | Err(Errno::EAGAIN) => Ok(()),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+// Full resyncs wait until there were no further announcements for this long, but a steady
+// stream of them can't hold off the resync for longer than the maximum delay
+const RESYNC_DELAY: Duration = Duration::from_millis(100);
+const RESYNC_MAX_DELAY: Duration = Duration::from_secs(1);
+
+// Renamed clients and ports are looked for this often, the sequencer doesn't announce them
+const RENAME_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+// Reopening the sequencer is retried with an exponential backoff between these delays
+const RETRY_MIN_DELAY: Duration = Duration::from_millis(500);
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(30);
+
+// The sequencer connection is supervised, errors are shown in the window title and the toolbar
+// and the connection is reopened, e.g. after the snd-seq module was reloaded. The thread
+// only stops when the UI is gone. Commands sent while reconnecting are rejected, the addresses
+// they name may belong to other ports by the time the sequencer is back.
+pub fn spawn_sequencer(app: Arc<Mutex<AppState>>, event_sender: EventSender) -> Result<SequencerHandle, Error> {
+    let (wake_read_fd, wake_write_fd) = unistd::pipe2(OFlag::O_NONBLOCK | OFlag::O_CLOEXEC)?;
+    let (command_sender, command_receiver) = mpsc::channel();
+
+    thread::spawn(move || -> Result<(), Error> {
+        let mut retry_delay = RETRY_MIN_DELAY;
+
+        loop {
+            let started_at = Instant::now();
+
+            if let Err(err) = run_sequencer(&app, &event_sender, &command_receiver, wake_read_fd) {
+                logging::error(&format!("Sequencer error: {}", err));
+                app.lock().unwrap().sequencer_lost(&err);
+                event_sender.push_custom_event(MidiPortChangeEvent).map_err(sdl_error)?;
+                reject_pending_commands(&command_receiver, &event_sender)?;
+            }
+
+            // A connection that worked for a while doesn't count as a failed retry
+            if started_at.elapsed() > RETRY_MAX_DELAY {
+                retry_delay = RETRY_MIN_DELAY;
+            }
+
+            thread::sleep(retry_delay);
+            retry_delay = (retry_delay * 2).min(RETRY_MAX_DELAY);
+            reject_pending_commands(&command_receiver, &event_sender)?;
+        }
+    });
+
+    Ok(SequencerHandle { commands: command_sender, wake_fd: wake_write_fd })
+}
+
+fn reject_pending_commands(commands: &Receiver<QueuedCommand>, event_sender: &EventSender) -> Result<(), Error> {
+    while let Ok(QueuedCommand { command, outcome_sender }) = commands.try_recv() {
+        let text = format!("{}: lost the connection to the sequencer", command.failure_caption());
+        logging::error(&text);
+
+        send_reply(event_sender, SequencerReply::Status(StatusMessage::error(&text)), outcome_sender)?;
+    }
+
+    Ok(())
+}
+
+// The UI shows the reply, the waiting caller only gets the outcome. A caller which gave up
+// waiting doesn't matter.
+fn send_reply(
+    event_sender: &EventSender,
+    reply: SequencerReply,
+    outcome_sender: Option<Sender<CommandOutcome>>,
+) -> Result<(), Error> {
+    if let Some(outcome_sender) = outcome_sender {
+        let outcome = match &reply {
+            SequencerReply::Status(status) if status.level == StatusLevel::Error => {
+                Err(Error::CommandError(status.text.clone()))
+            }
+            _ => Ok(()),
+        };
+        let _ = outcome_sender.send(outcome);
+    }
+
+    event_sender.push_custom_event(SequencerReplyEvent(reply)).map_err(sdl_error)
+}
+
+// Names our client and creates the port used for listening and for sending events
+pub fn create_client_port(seq: &Seq) -> Result<Addr, Error> {
+    let midi_name = CString::new(WINDOW_TITLE)?;
+    seq.set_client_name(&midi_name)?;
+
+    let mut port_info = PortInfo::empty()?;
+    // Reading is needed for sending events directly to the destinations, nobody can
+    // subscribe to our port for reading
+    port_info.set_capability(PortCap::READ | PortCap::WRITE | PortCap::SUBS_WRITE);
+    port_info.set_type(PortType::MIDI_GENERIC | PortType::APPLICATION);
+    port_info.set_name(&midi_name);
+    seq.create_port(&port_info)?;
+
+    Ok(port_info.addr())
+}
+
+// Every announcement moves the resync later, up to the maximum delay after the first one
+fn postpone_resync(resync_deadline: &mut Option<Instant>, resync_requested: &mut Option<Instant>) {
+    let now = Instant::now();
+    let first_request = *resync_requested.get_or_insert(now);
+    *resync_deadline = Some((now + RESYNC_DELAY).min(first_request + RESYNC_MAX_DELAY));
+}
+
+fn run_sequencer(
+    app: &Mutex<AppState>,
+    event_sender: &EventSender,
+    commands: &Receiver<QueuedCommand>,
+    wake_fd: RawFd,
+) -> Result<(), Error> {
+    let seq = Seq::open(None, None, false)?;
+    let client_port = create_client_port(&seq)?;
+
+    subscribe(&seq, Addr::system_announce(), client_port)?;
+
+    let snapshot = EndpointSnapshot::query(&seq)?;
+    logging::info(&format!("Connected to the sequencer as client {}", seq.client_id()?));
+    {
+        let mut app = app.lock().unwrap();
+        app.own_client = Some(seq.client_id()?);
+        if app.sequencer_error.take().is_some() {
+            app.status = Some(StatusMessage::info("Reconnected to the sequencer"));
+        }
+
+        app.apply_snapshot(snapshot);
+
+        // The subscriptions went away with the previous client, the monitored ports which are
+        // gone by now are not monitored anymore
+        app.listened_ports.clear();
+    }
+    sync_listened_ports(&seq, app, client_port);
+    event_sender.push_custom_event(MidiPortChangeEvent).map_err(sdl_error)?;
+
+    let mut fds = Vec::<alsa::poll::pollfd>::new();
+    fds.append(&mut (&seq, Some(alsa::Direction::Capture)).get()?);
+    let seq_fd_count = fds.len();
+    fds.push(alsa::poll::pollfd { fd: wake_fd, events: Flags::IN.bits(), revents: 0 });
+
+    // Full resyncs are postponed until a burst of announcements is over
+    let mut resync_deadline: Option<Instant> = None;
+    let mut resync_requested: Option<Instant> = None;
+    let mut next_rename_poll = Instant::now() + RENAME_POLL_INTERVAL;
+
+    let mut seq_input = seq.input();
+    loop {
+        let mut deltas = Vec::new();
+        let mut learned = false;
+
+        while seq_input.event_input_pending(true)? > 0 {
+            let event = seq_input.event_input()?;
+            if logging::enabled(LogLevel::Debug) {
+                logging::debug(&format!("Sequencer event: {:?}", event));
+            }
+            match event.get_type() {
+                EventType::PortStart => {
+                    // The port may be gone again by the time it's queried
+                    match event.get_data::<Addr>().map(|port_addr| PortEntry::query(&seq, port_addr)) {
+                        Some(Ok(port)) => deltas.push(EndpointDelta::PortStart(port)),
+                        _ => postpone_resync(&mut resync_deadline, &mut resync_requested),
+                    }
+                }
+                EventType::PortExit => {
+                    if let Some(port_addr) = event.get_data::<Addr>() {
+                        deltas.push(EndpointDelta::PortExit(port_addr));
+                    }
+                }
+                EventType::PortSubscribed => {
+                    if let Some(connect) = event.get_data::<Connect>() {
+                        deltas.push(EndpointDelta::Subscribed(connect.sender, connect.dest));
+                    }
+                }
+                EventType::PortUnsubscribed => {
+                    if let Some(connect) = event.get_data::<Connect>() {
+                        deltas.push(EndpointDelta::Unsubscribed(connect.sender, connect.dest));
+                    }
+                }
+                // Client announcements cover renamed clients, port renames are only
+                // detected by the periodic polling below
+                EventType::PortChange | EventType::ClientStart | EventType::ClientExit | EventType::ClientChange => {
+                    postpone_resync(&mut resync_deadline, &mut resync_requested);
+                }
+                _ => {
+                    let source = event.get_source();
+
+                    {
+                        let mut app = app.lock().unwrap();
+                        if app.monitored_ports.contains(&source) {
+                            // Bursts of events would flood the event queue, one redraw per flash is enough
+                            let flashing = app.port_active(source);
+                            app.port_activity.insert(source, Instant::now());
+
+                            if !flashing {
+                                event_sender.push_custom_event(MidiActivityEvent).map_err(sdl_error)?;
+                            }
+                        }
+                    }
+
+                    if let Some(trigger) = MidiTrigger::from_event(&event) {
+                        learned |= app.lock().unwrap().midi_learn.is_some();
+
+                        let status = AppState::handle_midi_trigger(app, source, trigger).unwrap_or_else(|err| {
+                            logging::error(&format!("Failed to execute the MIDI binding: {}", err));
+                            Some(StatusMessage::error(&format!("Failed to execute the MIDI binding: {}", err)))
+                        });
+
+                        if let Some(status) = status {
+                            let reply = SequencerReply::Status(status);
+                            event_sender.push_custom_event(SequencerReplyEvent(reply)).map_err(sdl_error)?;
+                        }
+                    }
+                }
+            }
+        }
+
+        if !deltas.is_empty() {
+            // Announcements about our own port, like the subscriptions of the monitored ports,
+            // are filtered out here, they don't change anything visible
+            let changed = {
+                let mut app = app.lock().unwrap();
+                let mut changed = false;
+                for delta in deltas {
+                    changed |= app.apply_delta(delta);
+                }
+
+                if changed {
+                    app.rebuild_endpoints();
+                }
+                changed
+            };
+
+            if changed {
+                sync_listened_ports(&seq, app, client_port);
+                event_sender.push_custom_event(MidiPortChangeEvent).map_err(sdl_error)?;
+            }
+        }
+
+        if next_rename_poll <= Instant::now() {
+            next_rename_poll = Instant::now() + RENAME_POLL_INTERVAL;
+
+            let port_keys = query_port_keys(&seq)?;
+            if app.lock().unwrap().port_names_changed(&port_keys) {
+                resync_deadline = Some(Instant::now());
+            }
+        }
+
+        if resync_deadline.map(|deadline| deadline <= Instant::now()).unwrap_or(false) {
+            resync_deadline = None;
+            resync_requested = None;
+
+            let snapshot = EndpointSnapshot::query(&seq)?;
+            app.lock().unwrap().apply_snapshot(snapshot);
+            sync_listened_ports(&seq, app, client_port);
+            event_sender.push_custom_event(MidiPortChangeEvent).map_err(sdl_error)?;
+        }
+
+        // The ports which were only listened to for learning are let go
+        if learned {
+            sync_listened_ports(&seq, app, client_port);
+        }
+
+        let next_wakeup = resync_deadline.unwrap_or(next_rename_poll).min(next_rename_poll);
+        let poll_timeout = next_wakeup.saturating_duration_since(Instant::now()).as_millis() as i32;
+        alsa::poll::poll(&mut fds, poll_timeout)?;
+
+        // A dead sequencer keeps the descriptor readable without ever delivering an event
+        let revents = (&seq, Some(alsa::Direction::Capture)).revents(&fds[..seq_fd_count])?;
+        if revents.intersects(Flags::ERR | Flags::HUP | Flags::NVAL) {
+            return Err(Error::GeneralError("lost connection to the sequencer"));
+        }
+
+        if fds[seq_fd_count].revents != 0 {
+            let mut wake_buffer = [0; 64];
+            while matches!(unistd::read(wake_fd, &mut wake_buffer), Ok(count) if count > 0) {}
+        }
+
+        while let Ok(QueuedCommand { command, outcome_sender }) = commands.try_recv() {
+            let failure_caption = command.failure_caption();
+
+            let reply = execute_command(&seq, app, client_port, command).unwrap_or_else(|err| {
+                logging::error(&format!("{}: {}", failure_caption, err));
+                SequencerReply::Status(StatusMessage::error(&format!("{}: {}", failure_caption, err)))
+            });
+
+            send_reply(event_sender, reply, outcome_sender)?;
+        }
+    }
+}
+
+// The connections are updated on a copy, so that consecutive changes see each other before the
+// announcements arrive. The state is only locked for reading the inputs and storing the
+// results, the UI isn't blocked while the sequencer is busy. Only this thread changes the
+// connections, the copy can't miss any change made meanwhile.
+fn execute_command(
+    seq: &Seq,
+    app: &Mutex<AppState>,
+    client_port: Addr,
+    command: SequencerCommand,
+) -> Result<SequencerReply, Error> {
+    match command {
+        SequencerCommand::Connect(connections) => {
+            let mut current_connections = app.lock().unwrap().connections.clone();
+
+            // The other pairs are still connected when one of them fails
+            let mut connected = Vec::new();
+            let mut removed_connections = Vec::new();
+            let mut failures = Vec::new();
+            for &(input_addr, output_addr) in &connections {
+                match routing::connect(seq, &mut current_connections, input_addr, output_addr) {
+                    Ok(removed) => {
+                        connected.push((input_addr, output_addr));
+                        removed_connections.extend(removed);
+                    }
+                    Err(err) => failures.push(((input_addr, output_addr), err)),
+                }
+            }
+
+            let mut app = app.lock().unwrap();
+            app.connections = current_connections;
+            Ok(SequencerReply::Status(app.routing_status(true, &connected, &removed_connections, &failures)))
+        }
+        SequencerCommand::Disconnect(connections) => {
+            let mut current_connections = app.lock().unwrap().connections.clone();
+
+            let mut disconnected = Vec::new();
+            let mut failures = Vec::new();
+            for &(input_addr, output_addr) in &connections {
+                match routing::disconnect(seq, &mut current_connections, input_addr, output_addr) {
+                    Ok(()) => disconnected.push((input_addr, output_addr)),
+                    Err(err) => failures.push(((input_addr, output_addr), err)),
+                }
+            }
+
+            let mut app = app.lock().unwrap();
+            app.connections = current_connections;
+            Ok(SequencerReply::Status(app.routing_status(false, &disconnected, &[], &failures)))
+        }
+        SequencerCommand::ToggleMonitor(port_addr) => {
+            let monitored = {
+                let mut app = app.lock().unwrap();
+                if app.monitored_ports.contains(&port_addr) {
+                    app.monitored_ports.retain(|&monitored_port| monitored_port != port_addr);
+                    false
+                } else {
+                    app.monitored_ports.push(port_addr);
+                    true
+                }
+            };
+
+            sync_listened_ports(seq, app, client_port);
+
+            let app = app.lock().unwrap();
+            let status = if !monitored {
+                format!("Stopped monitoring {}", app.port_name(port_addr))
+            } else if app.monitored_ports.contains(&port_addr) {
+                format!("Monitoring {}", app.port_name(port_addr))
+            } else {
+                return Err(Error::GeneralError("failed to subscribe to the port"));
+            };
+
+            Ok(SequencerReply::Status(StatusMessage::info(&status)))
+        }
+        SequencerCommand::QueryAttributes(input_addr, output_addr) => {
+            let attributes = routing::subscription_attributes(seq, input_addr, output_addr)
+                .ok_or(Error::GeneralError("subscription no longer exists"))?;
+
+            Ok(SequencerReply::Attributes(input_addr, output_addr, attributes))
+        }
+        SequencerCommand::ToggleAttribute(input_addr, output_addr, attribute) => {
+            let previous_attributes = routing::subscription_attributes(seq, input_addr, output_addr)
+                .ok_or(Error::GeneralError("subscription no longer exists"))?;
+
+            let mut attributes = previous_attributes;
+            attributes.toggle(attribute);
+            routing::resubscribe(seq, input_addr, output_addr, previous_attributes, attributes)?;
+
+            Ok(SequencerReply::Attributes(input_addr, output_addr, attributes))
+        }
+        SequencerCommand::LoadSnapshot(name, snapshot) => {
+            // Connections of hidden and filtered ports are left alone on both sides, like
+            // when the snapshot was captured
+            let (resolved_count, target_connections, mut diff, release_notes, mut current_connections) = {
+                let app = app.lock().unwrap();
+                let resolved_connections = snapshot.resolve(&app.ports);
+                let target_connections = resolved_connections
+                    .iter()
+                    .filter(|&&connection| app.matrix_connection(connection))
+                    .copied()
+                    .collect::<Vec<_>>();
+                let diff = SnapshotDiff::new(&app.matrix_connections(), &target_connections);
+                let release_notes = app.config.snapshots.release_notes;
+                (resolved_connections.len(), target_connections, diff, release_notes, app.connections.clone())
+            };
+
+            // Every change is applied on its own, a failing one doesn't leave the rest of the
+            // scene unapplied
+            let mut failures = Vec::new();
+
+            if release_notes {
+                let mut released_ports = Vec::new();
+                for &(_, output_addr) in &diff.removed {
+                    if !released_ports.contains(&output_addr) {
+                        if let Err(err) = routing::release_notes(seq, client_port, output_addr) {
+                            failures.push((SnapshotFailure::ReleaseNotes(output_addr), err));
+                        }
+                        released_ports.push(output_addr);
+                    }
+                }
+            }
+
+            // Removing first keeps the feedback loop resolution from touching connections which
+            // are about to go anyway
+            diff.removed.retain(|&(input_addr, output_addr)| {
+                match routing::disconnect(seq, &mut current_connections, input_addr, output_addr) {
+                    Ok(()) => true,
+                    Err(err) => {
+                        failures.push((SnapshotFailure::Disconnect(input_addr, output_addr), err));
+                        false
+                    }
+                }
+            });
+            let mut removed_connections = Vec::new();
+            diff.added.retain(|&(input_addr, output_addr)| {
+                match routing::connect(seq, &mut current_connections, input_addr, output_addr) {
+                    Ok(removed) => {
+                        removed_connections.extend(removed);
+                        true
+                    }
+                    Err(err) => {
+                        failures.push((SnapshotFailure::Connect(input_addr, output_addr), err));
+                        false
+                    }
+                }
+            });
+
+            let mut app = app.lock().unwrap();
+            app.connections = current_connections;
+
+            let mut status = format!("Loaded snapshot {}: {}", name, diff.summary());
+            let skipped_count = snapshot.connections.len() - resolved_count;
+            if skipped_count > 0 {
+                status.push_str(&format!(", skipped {} with missing ports", skipped_count));
+            }
+            let hidden_count = resolved_count - target_connections.len();
+            if hidden_count > 0 {
+                status.push_str(&format!(", skipped {} outside the matrix", hidden_count));
+            }
+            for connection in removed_connections {
+                status.push_str(&format!(", removed {} to break a feedback loop", app.connection_caption(connection)));
+            }
+            for (failure, err) in &failures {
+                let failure_caption = match *failure {
+                    SnapshotFailure::ReleaseNotes(output_addr) => {
+                        format!("release the notes of {}", app.port_name(output_addr))
+                    }
+                    SnapshotFailure::Disconnect(input_addr, output_addr) => {
+                        format!("disconnect {}", app.connection_caption((input_addr, output_addr)))
+                    }
+                    SnapshotFailure::Connect(input_addr, output_addr) => {
+                        format!("connect {}", app.connection_caption((input_addr, output_addr)))
+                    }
+                };
+                status.push_str(&format!(", failed to {}: {}", failure_caption, err));
+            }
+
+            app.current_snapshot = Some(name);
+            drop(app);
+
+            if failures.is_empty() {
+                logging::info(&status);
+                Ok(SequencerReply::Status(StatusMessage::info(&status)))
+            } else {
+                logging::error(&status);
+                Ok(SequencerReply::Status(StatusMessage::error(&status)))
+            }
+        }
+        SequencerCommand::LearnBinding(action) => {
+            let status = match &action {
+                Some(action) => {
+                    format!("Send a Program Change or CC to bind it to {}, Escape cancels", action.caption())
+                }
+                None => "Stopped learning the MIDI binding".to_owned(),
+            };
+
+            app.lock().unwrap().midi_learn = action;
+            sync_listened_ports(seq, app, client_port);
+
+            Ok(SequencerReply::Status(StatusMessage::info(&status)))
+        }
+        SequencerCommand::Panic(output_addrs) => {
+            Ok(SequencerReply::Status(send_panic(seq, app, client_port, &output_addrs)))
+        }
+    }
+}
+
+// The ports which can't be reached anymore don't stop the others from receiving the panic
+pub fn send_panic(seq: &Seq, app: &Mutex<AppState>, client_port: Addr, output_addrs: &[Addr]) -> StatusMessage {
+    let mut failed_ports = Vec::new();
+    for &output_addr in output_addrs {
+        if let Err(err) = routing::send_panic(seq, client_port, output_addr) {
+            failed_ports.push((output_addr, err));
+        }
+    }
+
+    let app = app.lock().unwrap();
+    let failure_messages = failed_ports
+        .iter()
+        .map(|(output_addr, err)| format!("Failed to send panic to {}: {}", app.port_caption(*output_addr, None), err))
+        .collect::<Vec<_>>();
+
+    let text = match output_addrs {
+        [output_addr] => format!("Sent panic to {}", app.port_name(*output_addr)),
+        _ => format!("Sent panic to {} ports", output_addrs.len()),
+    };
+    drop(app);
+
+    for message in failure_messages {
+        logging::error(&message);
+    }
+
+    if !failed_ports.is_empty() {
+        StatusMessage::error(&format!("{}, failed for {} of them", text, failed_ports.len()))
+    } else {
+        StatusMessage::info(&text)
+    }
+}
+
+// Subscribes our port to the ports it has to listen to and drops the subscriptions which are not
+// needed anymore. Monitored ports which can't be subscribed to are not monitored anymore.
+fn sync_listened_ports(seq: &Seq, app: &Mutex<AppState>, client_port: Addr) {
+    let (stale_ports, missing_ports) = {
+        let mut app_guard = app.lock().unwrap();
+        let app = &mut *app_guard;
+        let wanted_ports = app.wanted_listened_ports();
+
+        // The subscriptions of exited ports are gone with them
+        let ports = &app.ports;
+        app.listened_ports.retain(|&port_addr| ports.iter().any(|port| port.addr == port_addr));
+
+        let (listened_ports, stale_ports): (Vec<Addr>, Vec<Addr>) =
+            app.listened_ports.iter().partition(|port_addr| wanted_ports.contains(port_addr));
+        let missing_ports =
+            wanted_ports.into_iter().filter(|port_addr| !listened_ports.contains(port_addr)).collect::<Vec<Addr>>();
+        app.listened_ports = listened_ports;
+
+        (stale_ports, missing_ports)
+    };
+
+    for &port_addr in &stale_ports {
+        let _ = seq.unsubscribe_port(port_addr, client_port);
+    }
+
+    let results = missing_ports
+        .into_iter()
+        .map(|port_addr| (port_addr, subscribe(seq, port_addr, client_port)))
+        .collect::<Vec<_>>();
+
+    let mut failure_messages = Vec::new();
+    {
+        let mut app = app.lock().unwrap();
+        for (port_addr, result) in results {
+            match result {
+                Ok(()) => app.listened_ports.push(port_addr),
+                Err(err) => {
+                    failure_messages.push(format!(
+                        "Failed to listen to {}: {}",
+                        app.port_caption(port_addr, None),
+                        err
+                    ));
+                    app.monitored_ports.retain(|&monitored_port| monitored_port != port_addr);
+                }
+            }
+        }
+    }
+
+    for message in failure_messages {
+        logging::error(&message);
+    }
+}
+
+fn subscribe(seq: &Seq, sender: Addr, dest: Addr) -> Result<(), Error> {
+    let sub = PortSubscribe::empty()?;
+    sub.set_sender(sender);
+    sub.set_dest(dest);
+    seq.subscribe_port(&sub)?;
+    Ok(())
+}
@@ -0,0 +1,150 @@
+use std::fs;
+use std::path::PathBuf;
+
+use alsa::seq::Addr;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::endpoints::PortEntry;
+use crate::error::Error;
+
+// Ports are identified by their `client name:port name` keys, like in the config
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct SnapshotConnection {
+    pub sender: String,
+    pub dest: String,
+}
+
+// A saved routing set, stored as `<name>.toml` in the snapshots directory
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "kebab-case")]
+pub struct Snapshot {
+    pub connections: Vec<SnapshotConnection>,
+}
+
+impl Snapshot {
+    fn snapshots_directory() -> Result<PathBuf, Error> {
+        Ok(dirs::config_dir()
+            .ok_or(Error::GeneralError("failed to retrieve config directory"))?
+            .join("aseqmatrix")
+            .join("snapshots"))
+    }
+
+    fn snapshot_path(name: &str) -> Result<PathBuf, Error> {
+        // Names come from the control interfaces too, they must not escape the directory
+        if name.is_empty() || name.starts_with('.') || name.contains('/') {
+            return Err(Error::GeneralError("invalid snapshot name"));
+        }
+
+        Ok(Self::snapshots_directory()?.join(format!("{}.toml", name)))
+    }
+
+    // Sorted by name, the order is used for stepping through the snapshots
+    pub fn names() -> Result<Vec<String>, Error> {
+        let snapshots_directory = Self::snapshots_directory()?;
+        if !snapshots_directory.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut names = fs::read_dir(snapshots_directory)?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().map(|extension| extension == "toml").unwrap_or(false))
+            .filter_map(|path| path.file_stem().and_then(|stem| stem.to_str()).map(str::to_owned))
+            .collect::<Vec<String>>();
+
+        names.sort();
+        Ok(names)
+    }
+
+    pub fn load(name: &str) -> Result<Snapshot, Error> {
+        Ok(toml::from_slice(&fs::read(Self::snapshot_path(name)?)?)?)
+    }
+
+    pub fn save(&self, name: &str) -> Result<(), Error> {
+        let snapshot_path = Self::snapshot_path(name)?;
+
+        fs::create_dir_all(Self::snapshots_directory()?)?;
+        fs::write(snapshot_path, &toml::to_vec(self)?)?;
+
+        Ok(())
+    }
+
+    pub fn capture(ports: &[PortEntry], connections: &[(Addr, Addr)]) -> Snapshot {
+        let port_key = |port_addr: Addr| ports.iter().find(|port| port.addr == port_addr).map(PortEntry::key);
+
+        Snapshot {
+            connections: connections
+                .iter()
+                .filter_map(|&(sender, dest)| {
+                    Some(SnapshotConnection { sender: port_key(sender)?, dest: port_key(dest)? })
+                })
+                .collect(),
+        }
+    }
+
+    // Connections of ports which are not present at the moment are skipped
+    pub fn resolve(&self, ports: &[PortEntry]) -> Vec<(Addr, Addr)> {
+        let port_addr = |port_key: &str| ports.iter().find(|port| port.key() == port_key).map(|port| port.addr);
+
+        self.connections
+            .iter()
+            .filter_map(|connection| Some((port_addr(&connection.sender)?, port_addr(&connection.dest)?)))
+            .collect()
+    }
+}
+
+// The changes leading from the current connections to the ones of a snapshot, the connections
+// present in both are left alone
+pub struct SnapshotDiff {
+    pub added: Vec<(Addr, Addr)>,
+    pub removed: Vec<(Addr, Addr)>,
+    pub unchanged: usize,
+}
+
+impl SnapshotDiff {
+    pub fn new(current: &[(Addr, Addr)], target: &[(Addr, Addr)]) -> SnapshotDiff {
+        let mut added = Vec::new();
+        for connection in target {
+            if !current.contains(connection) && !added.contains(connection) {
+                added.push(*connection);
+            }
+        }
+
+        let removed = current.iter().filter(|connection| !target.contains(connection)).copied().collect::<Vec<_>>();
+
+        let unchanged = current.len() - removed.len();
+        SnapshotDiff { added, removed, unchanged }
+    }
+
+    pub fn summary(&self) -> String {
+        if self.added.is_empty() && self.removed.is_empty() {
+            return "nothing to change".to_owned();
+        }
+
+        format!("connected {}, disconnected {}, kept {}", self.added.len(), self.removed.len(), self.unchanged)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn connection(sender: i32, dest: i32) -> (Addr, Addr) {
+        (Addr { client: sender, port: 0 }, Addr { client: dest, port: 0 })
+    }
+
+    #[test]
+    fn only_the_differences_are_applied() {
+        let current = [connection(20, 128), connection(20, 129), connection(24, 128)];
+        let target = [connection(20, 128), connection(24, 129), connection(24, 129)];
+
+        let diff = SnapshotDiff::new(&current, &target);
+        assert_eq!(diff.added, vec![connection(24, 129)]);
+        assert_eq!(diff.removed, vec![connection(20, 129), connection(24, 128)]);
+        assert_eq!(diff.unchanged, 1);
+        assert_eq!(diff.summary(), "connected 1, disconnected 2, kept 1");
+
+        assert_eq!(SnapshotDiff::new(&current, &current).summary(), "nothing to change");
+    }
+}
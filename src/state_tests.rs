@@ -0,0 +1,175 @@
+use alsa::seq::{Addr, PortCap};
+
+use crate::config::{AppConfig, SortMode};
+use crate::endpoints::{EndpointDelta, PortEntry};
+use crate::AppState;
+
+const NANOKEY: &str = "nanoKEY2:nanoKEY2 MIDI 1";
+const KEYSTEP: &str = "Arturia KeyStep 32:Arturia KeyStep 32";
+const SURGE: &str = "Surge XT:Surge XT Output";
+const FLUIDSYNTH: &str = "FluidSynth:Synth input port";
+
+fn port(client: i32, client_name: &str, name: &str) -> PortEntry {
+    PortEntry {
+        addr: Addr { client, port: 0 },
+        client_name: client_name.to_owned(),
+        name: name.to_owned(),
+        capability: PortCap::SUBS_READ | PortCap::SUBS_WRITE,
+    }
+}
+
+// Every port is both a row and a column
+fn mock_app_state(config: AppConfig) -> AppState {
+    let mut app = AppState::with_config(config);
+
+    app.ports = vec![
+        port(20, "nanoKEY2", "nanoKEY2 MIDI 1"),
+        port(24, "Arturia KeyStep 32", "Arturia KeyStep 32"),
+        port(128, "Surge XT", "Surge XT Output"),
+        port(130, "FluidSynth", "Synth input port"),
+    ];
+    app.rebuild_endpoints();
+
+    app
+}
+
+fn keys(keys: &[&str]) -> Vec<String> {
+    keys.iter().map(|key| key.to_string()).collect()
+}
+
+fn input_clients(app: &AppState) -> Vec<i32> {
+    app.inputs.iter().map(|(port_addr, _)| port_addr.client).collect()
+}
+
+fn output_clients(app: &AppState) -> Vec<i32> {
+    app.outputs.iter().map(|(port_addr, _)| port_addr.client).collect()
+}
+
+fn addr(client: i32) -> Addr {
+    Addr { client, port: 0 }
+}
+
+#[test]
+fn ports_are_sorted_by_the_sort_mode() {
+    let app = mock_app_state(AppConfig::default());
+    assert_eq!(input_clients(&app), vec![20, 24, 128, 130]);
+
+    let app = mock_app_state(AppConfig { sort_mode: SortMode::Name, ..AppConfig::default() });
+    assert_eq!(input_clients(&app), vec![24, 20, 128, 130]);
+    assert_eq!(output_clients(&app), vec![24, 20, 128, 130]);
+
+    // Unlisted ports follow the listed ones, the rows and the columns have their own order
+    let app = mock_app_state(AppConfig {
+        sort_mode: SortMode::UserDefined,
+        input_port_order: keys(&[FLUIDSYNTH, NANOKEY]),
+        output_port_order: keys(&[KEYSTEP]),
+        ..AppConfig::default()
+    });
+    assert_eq!(input_clients(&app), vec![130, 20, 24, 128]);
+    assert_eq!(output_clients(&app), vec![24, 20, 128, 130]);
+
+    let mut app = mock_app_state(AppConfig { sort_mode: SortMode::ConnectedFirst, ..AppConfig::default() });
+    app.connections = vec![(addr(128), addr(24))];
+    app.sort_ports(&[], &[]);
+    assert_eq!(input_clients(&app), vec![128, 20, 24, 130]);
+    assert_eq!(output_clients(&app), vec![24, 20, 128, 130]);
+}
+
+#[test]
+fn pinned_ports_stay_in_front() {
+    for sort_mode in SortMode::ALL {
+        let app = mock_app_state(AppConfig {
+            sort_mode,
+            pinned_ports: keys(&[FLUIDSYNTH, SURGE]),
+            input_port_order: keys(&[KEYSTEP, SURGE]),
+            ..AppConfig::default()
+        });
+
+        assert_eq!(&input_clients(&app)[..2], &[130, 128]);
+        assert_eq!(&output_clients(&app)[..2], &[130, 128]);
+    }
+}
+
+#[test]
+fn ports_are_moved_within_their_group() {
+    let mut app = mock_app_state(AppConfig { pinned_ports: keys(&[SURGE, FLUIDSYNTH]), ..AppConfig::default() });
+    assert_eq!(input_clients(&app), vec![128, 130, 20, 24]);
+
+    // Moving a pinned port only reorders the pins
+    app.reorder_port(addr(130), true, -5);
+    assert_eq!(app.config.pinned_ports, keys(&[FLUIDSYNTH, SURGE]));
+    assert!(app.config.sort_mode == SortMode::ClientId);
+    assert_eq!(input_clients(&app), vec![130, 128, 20, 24]);
+
+    app.reorder_port(addr(128), true, 1);
+    assert_eq!(input_clients(&app), vec![130, 128, 20, 24]);
+
+    // Unpinned ports can't be moved in front of the pinned ones
+    app.reorder_port(addr(20), true, -1);
+    assert_eq!(input_clients(&app), vec![130, 128, 20, 24]);
+
+    app.reorder_port(addr(24), true, -1);
+    assert!(app.config.sort_mode == SortMode::UserDefined);
+    assert_eq!(input_clients(&app), vec![130, 128, 24, 20]);
+    assert_eq!(output_clients(&app), vec![130, 128, 20, 24]);
+
+    app.reorder_port(addr(20), false, 5);
+    assert_eq!(output_clients(&app), vec![130, 128, 24, 20]);
+    assert_eq!(app.config.input_port_order, keys(&[KEYSTEP, NANOKEY]));
+    assert_eq!(app.config.output_port_order, keys(&[KEYSTEP, NANOKEY]));
+}
+
+#[test]
+fn absent_ports_keep_their_place() {
+    let mut app = mock_app_state(AppConfig {
+        sort_mode: SortMode::UserDefined,
+        pinned_ports: keys(&["Gone:Pinned", SURGE, "Gone:Also pinned", FLUIDSYNTH]),
+        input_port_order: keys(&["Gone:Port", NANOKEY, KEYSTEP]),
+        output_port_order: keys(&[KEYSTEP, NANOKEY, "Gone:Port"]),
+        ..AppConfig::default()
+    });
+
+    app.reorder_port(addr(130), true, -1);
+    assert_eq!(app.config.pinned_ports, keys(&["Gone:Pinned", FLUIDSYNTH, "Gone:Also pinned", SURGE]));
+
+    app.reorder_port(addr(24), true, -1);
+    assert_eq!(app.config.input_port_order, keys(&["Gone:Port", KEYSTEP, NANOKEY]));
+
+    app.reorder_port(addr(20), false, -1);
+    assert_eq!(app.config.output_port_order, keys(&["Gone:Port", NANOKEY, KEYSTEP]));
+}
+
+#[test]
+fn exited_ports_lose_their_connections() {
+    let mut app = mock_app_state(AppConfig::default());
+    app.connections = vec![(addr(20), addr(128)), (addr(24), addr(130)), (addr(128), addr(20))];
+
+    assert!(app.apply_delta(EndpointDelta::PortExit(addr(20))));
+    assert_eq!(app.connections, vec![(addr(24), addr(130))]);
+    assert!(app.ports.iter().all(|port| port.addr != addr(20)));
+}
+
+#[test]
+fn subscriptions_are_only_added_once() {
+    let mut app = mock_app_state(AppConfig::default());
+    app.own_client = Some(129);
+
+    assert!(app.apply_delta(EndpointDelta::Subscribed(addr(20), addr(128))));
+    assert!(!app.apply_delta(EndpointDelta::Subscribed(addr(20), addr(128))));
+    // The subscriptions of our own port are filtered out
+    assert!(!app.apply_delta(EndpointDelta::Subscribed(addr(20), addr(129))));
+
+    assert_eq!(app.connections, vec![(addr(20), addr(128))]);
+}
+
+#[test]
+fn unknown_unsubscriptions_change_nothing() {
+    let mut app = mock_app_state(AppConfig::default());
+    app.connections = vec![(addr(20), addr(128))];
+
+    assert!(!app.apply_delta(EndpointDelta::Unsubscribed(addr(128), addr(20))));
+    assert!(app.apply_delta(EndpointDelta::Unsubscribed(addr(20), addr(128))));
+    assert!(!app.apply_delta(EndpointDelta::Unsubscribed(addr(20), addr(128))));
+
+    assert!(app.connections.is_empty());
+}
@@ -0,0 +1,22 @@
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum StatusLevel {
+    Info,
+    Error,
+}
+
+// The outcome of the last action, or a non-fatal error, shown in the toolbar. It stays until
+// the next message replaces it or the toolbar is clicked.
+pub struct StatusMessage {
+    pub level: StatusLevel,
+    pub text: String,
+}
+
+impl StatusMessage {
+    pub fn info(text: &str) -> StatusMessage {
+        StatusMessage { level: StatusLevel::Info, text: text.to_owned() }
+    }
+
+    pub fn error(text: &str) -> StatusMessage {
+        StatusMessage { level: StatusLevel::Error, text: text.to_owned() }
+    }
+}
@@ -1,13 +1,16 @@
-use std::fs;
 use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use std::{fmt, fs, iter};
 
 use sdl2::image::LoadTexture;
+use sdl2::pixels::Color;
 use sdl2::render::{Texture, TextureCreator};
-use sdl2::video::WindowContext;
+use serde::de::{Deserializer, Error as _};
 use serde_derive::Deserialize;
 
 use crate::error::{sdl_error, Error};
-use crate::graphics::{TileDimension, TileRect, TileTexture};
+use crate::graphics::{PixelDimension, TileDimension, TileRect, TileTexture};
+use crate::logging;
 
 #[derive(Deserialize)]
 #[serde(rename_all = "kebab-case")]
@@ -24,6 +27,272 @@ pub struct ThemeMetadata {
 pub struct ThemeMetrics {
     pub window_margin: usize,
     pub label_spacing: usize,
+    #[serde(default)]
+    pub arrow_spacing: usize,
+    #[serde(default)]
+    pub grid_spacing: usize,
+}
+
+#[derive(Copy, Clone)]
+pub struct ThemeColor(pub Color);
+
+impl ThemeColor {
+    fn parse(color: &str) -> Option<ThemeColor> {
+        // `from_str_radix` would accept a sign too
+        let hex = color.strip_prefix('#').filter(|hex| hex.bytes().all(|byte| byte.is_ascii_hexdigit()))?;
+        let component = |index: usize| u8::from_str_radix(hex.get(index * 2..index * 2 + 2)?, 16).ok();
+
+        match hex.len() {
+            6 => Some(ThemeColor(Color::RGB(component(0)?, component(1)?, component(2)?))),
+            8 => Some(ThemeColor(Color::RGBA(component(0)?, component(1)?, component(2)?, component(3)?))),
+            _ => None,
+        }
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for ThemeColor {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let color = <String as serde::Deserialize>::deserialize(deserializer)?;
+        ThemeColor::parse(&color)
+            .ok_or_else(|| D::Error::custom(format!("invalid color `{}`, expected `#rrggbb` or `#rrggbbaa`", color)))
+    }
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "kebab-case")]
+pub struct ThemeLabelColors {
+    pub normal: Option<ThemeColor>,
+    pub hover: Option<ThemeColor>,
+    pub selected: Option<ThemeColor>,
+    pub error: Option<ThemeColor>,
+}
+
+impl ThemeLabelColors {
+    pub fn color(&self, hovered: bool, selected: bool) -> Color {
+        let label_color = match (hovered, selected) {
+            (true, true) => self.selected.or(self.hover),
+            (true, false) => self.hover,
+            (false, _) => None,
+        };
+
+        label_color.or(self.normal).map(|ThemeColor(color)| color).unwrap_or(Color::WHITE)
+    }
+
+    pub fn error_color(&self) -> Color {
+        self.error.map(|ThemeColor(color)| color).unwrap_or(Color::RGB(255, 96, 96))
+    }
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "kebab-case")]
+pub struct ThemeShading {
+    pub rows: Option<ThemeColor>,
+    pub columns: Option<ThemeColor>,
+}
+
+#[derive(Deserialize, Copy, Clone, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ThemeLabelStyle {
+    Bold,
+    Inverted,
+}
+
+#[derive(Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct ThemeCrosshair {
+    pub tint: ThemeColor,
+    pub label_style: ThemeLabelStyle,
+    pub inverted_text: ThemeColor,
+}
+
+impl Default for ThemeCrosshair {
+    fn default() -> ThemeCrosshair {
+        ThemeCrosshair {
+            tint: ThemeColor(Color::RGBA(255, 255, 255, 48)),
+            label_style: ThemeLabelStyle::Bold,
+            inverted_text: ThemeColor(Color::BLACK),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct ThemeControls {
+    pub tiles: TileDimension,
+    pub arrow_left_normal: TileRect,
+    pub arrow_left_active: TileRect,
+    pub arrow_down_normal: TileRect,
+    pub arrow_down_active: TileRect,
+    pub button_normal: TileRect,
+    pub button_active: TileRect,
+    pub button_disabled: TileRect,
+    pub button_normal_hover: TileRect,
+    pub button_active_hover: TileRect,
+    pub button_disabled_hover: TileRect,
+    pub button_normal_down: TileRect,
+    pub button_active_down: TileRect,
+    pub button_disabled_down: TileRect,
+    pub crosshair_row: Option<TileRect>,
+    pub crosshair_column: Option<TileRect>,
+    // Lit on the arrows of the monitored ports while they send events
+    pub arrow_down_activity: Option<TileRect>,
+}
+
+impl Default for ThemeControls {
+    fn default() -> ThemeControls {
+        ThemeControls {
+            tiles: TileDimension { width: 16, height: 16 },
+            arrow_left_normal: Theme::RECT_ARROW_LEFT_NORMAL,
+            arrow_left_active: Theme::RECT_ARROW_LEFT_ACTIVE,
+            arrow_down_normal: Theme::RECT_ARROW_DOWN_NORMAL,
+            arrow_down_active: Theme::RECT_ARROW_DOWN_ACTIVE,
+            button_normal: Theme::RECT_BUTTON_NORMAL,
+            button_active: Theme::RECT_BUTTON_ACTIVE,
+            button_disabled: Theme::RECT_BUTTON_DISABLED,
+            button_normal_hover: Theme::RECT_BUTTON_NORMAL_HOVER,
+            button_active_hover: Theme::RECT_BUTTON_ACTIVE_HOVER,
+            button_disabled_hover: Theme::RECT_BUTTON_DISABLED_HOVER,
+            button_normal_down: Theme::RECT_BUTTON_NORMAL_DOWN,
+            button_active_down: Theme::RECT_BUTTON_ACTIVE_DOWN,
+            button_disabled_down: Theme::RECT_BUTTON_DISABLED_DOWN,
+            crosshair_row: None,
+            crosshair_column: None,
+            arrow_down_activity: None,
+        }
+    }
+}
+
+impl ThemeControls {
+    fn rects(&self) -> [TileRect; 13] {
+        [
+            self.arrow_left_normal,
+            self.arrow_left_active,
+            self.arrow_down_normal,
+            self.arrow_down_active,
+            self.button_normal,
+            self.button_active,
+            self.button_disabled,
+            self.button_normal_hover,
+            self.button_active_hover,
+            self.button_disabled_hover,
+            self.button_normal_down,
+            self.button_active_down,
+            self.button_disabled_down,
+        ]
+    }
+
+    // Every state of a control is drawn into the same cell, it has to match the size of the
+    // normal state
+    fn validate_sizes(&self, animations: &ThemeAnimations) -> Result<(), Error> {
+        let button_frames = [
+            &animations.button_normal_hover,
+            &animations.button_active_hover,
+            &animations.button_disabled_hover,
+            &animations.button_normal_down,
+            &animations.button_active_down,
+            &animations.button_disabled_down,
+        ];
+        let button_rects = [
+            self.button_active,
+            self.button_disabled,
+            self.button_normal_hover,
+            self.button_active_hover,
+            self.button_disabled_hover,
+            self.button_normal_down,
+            self.button_active_down,
+            self.button_disabled_down,
+        ]
+        .into_iter()
+        .chain([self.crosshair_row, self.crosshair_column].into_iter().flatten())
+        .chain(button_frames.into_iter().flatten().copied());
+        if !same_size(self.button_normal, button_rects) {
+            return Err(Error::GeneralError("theme button rects must match the size of button-normal"));
+        }
+
+        let arrow_left_rects = iter::once(self.arrow_left_active).chain(animations.arrow_left_active.iter().copied());
+        if !same_size(self.arrow_left_normal, arrow_left_rects) {
+            return Err(Error::GeneralError("theme arrow-left rects must match the size of arrow-left-normal"));
+        }
+
+        let arrow_down_rects = [Some(self.arrow_down_active), self.arrow_down_activity]
+            .into_iter()
+            .flatten()
+            .chain(animations.arrow_down_active.iter().copied());
+        if !same_size(self.arrow_down_normal, arrow_down_rects) {
+            return Err(Error::GeneralError("theme arrow-down rects must match the size of arrow-down-normal"));
+        }
+
+        Ok(())
+    }
+}
+
+fn same_size(normal: TileRect, mut rects: impl Iterator<Item = TileRect>) -> bool {
+    rects.all(|rect| (rect.width == normal.width) && (rect.height == normal.height))
+}
+
+#[derive(Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct ThemeAnimations {
+    pub frame_duration: u64,
+    pub arrow_left_active: Vec<TileRect>,
+    pub arrow_down_active: Vec<TileRect>,
+    pub button_normal_hover: Vec<TileRect>,
+    pub button_active_hover: Vec<TileRect>,
+    pub button_disabled_hover: Vec<TileRect>,
+    pub button_normal_down: Vec<TileRect>,
+    pub button_active_down: Vec<TileRect>,
+    pub button_disabled_down: Vec<TileRect>,
+}
+
+impl Default for ThemeAnimations {
+    fn default() -> ThemeAnimations {
+        ThemeAnimations {
+            frame_duration: 40,
+            arrow_left_active: Vec::new(),
+            arrow_down_active: Vec::new(),
+            button_normal_hover: Vec::new(),
+            button_active_hover: Vec::new(),
+            button_disabled_hover: Vec::new(),
+            button_normal_down: Vec::new(),
+            button_active_down: Vec::new(),
+            button_disabled_down: Vec::new(),
+        }
+    }
+}
+
+impl ThemeAnimations {
+    fn animations(&self) -> [&Vec<TileRect>; 8] {
+        [
+            &self.arrow_left_active,
+            &self.arrow_down_active,
+            &self.button_normal_hover,
+            &self.button_active_hover,
+            &self.button_disabled_hover,
+            &self.button_normal_down,
+            &self.button_active_down,
+            &self.button_disabled_down,
+        ]
+    }
+
+    pub fn frame_duration(&self) -> Duration {
+        Duration::from_millis(self.frame_duration.max(1))
+    }
+
+    pub fn longest_duration(&self) -> Duration {
+        let longest_animation = self.animations().iter().map(|frames| frames.len()).max().unwrap_or(0);
+        self.frame_duration() * longest_animation as u32
+    }
+
+    // Returns `None` once the transition is over and the static rect of the state should be used
+    pub fn frame(&self, frames: &[TileRect], started: Instant, reverse: bool) -> Option<TileRect> {
+        let frame_index = (started.elapsed().as_millis() / self.frame_duration().as_millis()) as usize;
+
+        if frame_index < frames.len() {
+            Some(if reverse { frames[frames.len() - 1 - frame_index] } else { frames[frame_index] })
+        } else {
+            None
+        }
+    }
 }
 
 #[derive(Deserialize)]
@@ -32,6 +301,45 @@ pub struct ThemeManifest {
     #[serde(rename = "theme")]
     pub metadata: ThemeMetadata,
     pub metrics: ThemeMetrics,
+    #[serde(default)]
+    pub controls: ThemeControls,
+    #[serde(default)]
+    pub animations: ThemeAnimations,
+    #[serde(default)]
+    pub label_colors: ThemeLabelColors,
+    #[serde(default)]
+    pub shading: ThemeShading,
+    #[serde(default)]
+    pub crosshair: ThemeCrosshair,
+}
+
+impl ThemeManifest {
+    pub fn load(manifest_path: &Path) -> Result<ThemeManifest, Error> {
+        Ok(toml::from_slice(&fs::read(manifest_path)?)?)
+    }
+}
+
+#[derive(Clone)]
+pub enum ThemeLocation {
+    Builtin,
+    System(PathBuf),
+    User(PathBuf),
+}
+
+impl fmt::Display for ThemeLocation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Self::Builtin => write!(f, "builtin"),
+            Self::System(ref path) => write!(f, "{}", path.display()),
+            Self::User(ref path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+pub struct ThemeEntry {
+    pub manifest_path: PathBuf,
+    pub location: ThemeLocation,
+    pub metadata: ThemeMetadata,
 }
 
 pub struct Theme<'a> {
@@ -61,9 +369,30 @@ impl<'a> Theme<'a> {
     pub const RECT_BUTTON_ACTIVE_DOWN: TileRect = TileRect { x: 4, y: 2, width: 2, height: 2 };
     pub const RECT_BUTTON_DISABLED_DOWN: TileRect = TileRect { x: 4, y: 4, width: 2, height: 2 };
 
-    pub fn new(texture_creator: &'a TextureCreator<WindowContext>, manifest_path: &Path) -> Result<Theme<'a>, Error> {
+    pub fn new<C>(texture_creator: &'a TextureCreator<C>, manifest_path: &Path) -> Result<Theme<'a>, Error> {
         // TODO: check width, height mod
-        let manifest = toml::from_slice(&fs::read(&manifest_path)?)?;
+        let manifest = ThemeManifest::load(manifest_path)?;
+
+        let controls_tiles = manifest.controls.tiles;
+        if (controls_tiles.width == 0) || (controls_tiles.height == 0) {
+            return Err(Error::GeneralError("theme controls grid must have at least one tile"));
+        }
+
+        let animation_frames = manifest.animations.animations().into_iter().flatten().copied();
+        let optional_rects = [
+            manifest.controls.crosshair_row,
+            manifest.controls.crosshair_column,
+            manifest.controls.arrow_down_activity,
+        ]
+        .into_iter()
+        .flatten();
+        for rect in manifest.controls.rects().into_iter().chain(animation_frames).chain(optional_rects) {
+            if (rect.x + rect.width > controls_tiles.width) || (rect.y + rect.height > controls_tiles.height) {
+                return Err(Error::GeneralError("theme control rect lies outside of the controls grid"));
+            }
+        }
+        manifest.controls.validate_sizes(&manifest.animations)?;
+
         let theme_directory = manifest_path
             .parent()
             .ok_or(Error::GeneralError("failed to retrieve parent directory of theme manifest path"))?;
@@ -71,11 +400,7 @@ impl<'a> Theme<'a> {
         let background_texture =
             texture_creator.load_texture(theme_directory.join("background.png")).map_err(sdl_error)?;
 
-        let controls_texture = TileTexture::new(
-            texture_creator,
-            theme_directory.join("controls.png"),
-            TileDimension { width: 16, height: 16 },
-        )?;
+        let controls_texture = TileTexture::new(texture_creator, theme_directory.join("controls.png"), controls_tiles)?;
         let font_texture = TileTexture::new(
             texture_creator,
             theme_directory.join("font.png"),
@@ -90,7 +415,19 @@ impl<'a> Theme<'a> {
         Ok(Theme { manifest, background_texture, controls_texture, font_texture, borders_texture })
     }
 
-    pub fn theme_manifest_paths() -> Result<Vec<PathBuf>, Error> {
+    pub fn button_dimensions(&self) -> PixelDimension {
+        self.controls_texture.rect_dimensions(self.manifest.controls.button_normal)
+    }
+
+    pub fn arrow_left_dimensions(&self) -> PixelDimension {
+        self.controls_texture.rect_dimensions(self.manifest.controls.arrow_left_normal)
+    }
+
+    pub fn arrow_down_dimensions(&self) -> PixelDimension {
+        self.controls_texture.rect_dimensions(self.manifest.controls.arrow_down_normal)
+    }
+
+    pub fn available_themes() -> Result<Vec<ThemeEntry>, Error> {
         let themes_directory_builtin = PathBuf::from("themes");
         let themes_directory_system = PathBuf::from("/usr/share/aseqmatrix/themes");
         let themes_directory_user = dirs::data_dir()
@@ -111,11 +448,51 @@ impl<'a> Theme<'a> {
             }
         }
 
-        let mut manifest_paths = Vec::new();
-        manifest_paths.append(&mut scan_theme_directory(&themes_directory_builtin)?);
-        manifest_paths.append(&mut scan_theme_directory(&themes_directory_system)?);
-        manifest_paths.append(&mut scan_theme_directory(&themes_directory_user)?);
-        manifest_paths.sort();
-        Ok(manifest_paths)
+        let mut theme_entries = Vec::new();
+        for (themes_directory, location) in [
+            (&themes_directory_builtin, ThemeLocation::Builtin),
+            (&themes_directory_system, ThemeLocation::System(themes_directory_system.clone())),
+            (&themes_directory_user, ThemeLocation::User(themes_directory_user.clone())),
+        ] {
+            for manifest_path in scan_theme_directory(themes_directory)? {
+                // A single broken manifest shouldn't hide the rest of the themes
+                match ThemeManifest::load(&manifest_path) {
+                    Ok(manifest) => theme_entries.push(ThemeEntry {
+                        manifest_path,
+                        location: location.clone(),
+                        metadata: manifest.metadata,
+                    }),
+                    Err(err) => {
+                        logging::error(&format!("Failed to load theme manifest {}: {}", manifest_path.display(), err))
+                    }
+                }
+            }
+        }
+
+        theme_entries.sort_by(|a, b| a.manifest_path.cmp(&b.manifest_path));
+        Ok(theme_entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parsed_color(color: &str) -> Option<Color> {
+        ThemeColor::parse(color).map(|ThemeColor(color)| color)
+    }
+
+    #[test]
+    fn colors_are_parsed() {
+        assert_eq!(parsed_color("#1a2B3c"), Some(Color::RGBA(0x1a, 0x2b, 0x3c, 0xff)));
+        assert_eq!(parsed_color("#1a2b3c80"), Some(Color::RGBA(0x1a, 0x2b, 0x3c, 0x80)));
+
+        assert_eq!(parsed_color("1a2b3c"), None);
+        assert_eq!(parsed_color("#1a2b3"), None);
+        assert_eq!(parsed_color("#1a2b3c8"), None);
+        assert_eq!(parsed_color("#"), None);
+        assert_eq!(parsed_color("#1a2b3g"), None);
+        assert_eq!(parsed_color("#+a2b3c"), None);
+        assert_eq!(parsed_color("#1a2b\u{e9}"), None);
     }
 }
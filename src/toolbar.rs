@@ -0,0 +1,82 @@
+use sdl2::render::{Canvas, RenderTarget};
+
+use crate::error::Error;
+use crate::graphics::{draw_colored_string, draw_frame, PixelDimension, PixelPosition, PixelRect};
+use crate::status::{StatusLevel, StatusMessage};
+use crate::theme::Theme;
+
+const PANIC_CAPTION: &str = "[Panic]";
+
+// The strip below the matrix, it's always shown. The panic button is at its right end, the
+// status message, if there's one, takes the rest of it.
+pub fn height(theme: &Theme) -> usize {
+    theme.font_texture.tile_size.height + 2 * theme.borders_texture.tile_size.height
+}
+
+// Sends the panic to every output port
+pub fn panic_button_rect(theme: &Theme, rect: PixelRect) -> PixelRect {
+    let width = PANIC_CAPTION.chars().count() * theme.font_texture.tile_size.width;
+
+    PixelRect {
+        x: rect.x + rect.width as isize - (theme.borders_texture.tile_size.width + width) as isize,
+        y: rect.y + theme.borders_texture.tile_size.height as isize,
+        width,
+        height: theme.font_texture.tile_size.height,
+    }
+}
+
+// Messages longer than the toolbar are cut off, the window is never widened for them. One
+// character is kept free before the panic button.
+fn visible_text(text: &str, theme: &Theme, width: usize) -> String {
+    let text_length = (width.saturating_sub(2 * theme.borders_texture.tile_size.width)
+        / theme.font_texture.tile_size.width)
+        .saturating_sub(PANIC_CAPTION.chars().count() + 1);
+
+    if text.chars().count() <= text_length {
+        text.to_owned()
+    } else {
+        let mut visible_text = text.chars().take(text_length.saturating_sub(3)).collect::<String>();
+        visible_text.push_str("...");
+        visible_text
+    }
+}
+
+pub fn render<T: RenderTarget>(
+    canvas: &mut Canvas<T>,
+    theme: &mut Theme,
+    rect: PixelRect,
+    status: Option<&StatusMessage>,
+) -> Result<(), Error> {
+    draw_frame(
+        canvas,
+        &theme.borders_texture,
+        rect.position(),
+        PixelDimension { width: rect.width, height: rect.height },
+    )?;
+
+    if let Some(status) = status {
+        let text_position = PixelPosition {
+            x: rect.x + theme.borders_texture.tile_size.width as isize,
+            y: rect.y + theme.borders_texture.tile_size.height as isize,
+        };
+
+        let color = match status.level {
+            StatusLevel::Info => theme.manifest.label_colors.color(false, false),
+            StatusLevel::Error => theme.manifest.label_colors.error_color(),
+        };
+
+        let text = visible_text(&status.text, theme, rect.width);
+        draw_colored_string(canvas, &mut theme.font_texture, &text, text_position, 0, color)?;
+    }
+
+    let panic_button_rect = panic_button_rect(theme, rect);
+    let panic_button_color = theme.manifest.label_colors.error_color();
+    draw_colored_string(
+        canvas,
+        &mut theme.font_texture,
+        PANIC_CAPTION,
+        panic_button_rect.position(),
+        0,
+        panic_button_color,
+    )
+}
@@ -0,0 +1,79 @@
+use std::path::Path;
+use std::thread;
+
+use nix::sys::inotify::{AddWatchFlags, InitFlags, Inotify, WatchDescriptor};
+use sdl2::event::EventSender;
+
+use crate::error::{sdl_error, Error};
+use crate::logging;
+use crate::status::StatusMessage;
+
+pub struct ThemeChangeEvent;
+
+// Hot-reloading stops on errors, the status tells why
+pub struct ThemeWatchErrorEvent(pub StatusMessage);
+
+pub struct ThemeWatcher {
+    inotify: Inotify,
+    watch_descriptor: Option<WatchDescriptor>,
+}
+
+impl ThemeWatcher {
+    const WATCHED_FILES: [&'static str; 5] =
+        ["theme.toml", "background.png", "controls.png", "font.png", "borders.png"];
+
+    pub fn new(event_sender: EventSender) -> Result<ThemeWatcher, Error> {
+        let inotify = Inotify::init(InitFlags::IN_CLOEXEC)?;
+
+        thread::spawn(move || {
+            if let Err(err) = Self::watch_events(inotify, &event_sender) {
+                let message = format!("Stopped watching the theme for changes: {}", err);
+                logging::error(&message);
+                // Nothing can be shown when the event queue itself failed
+                let _ = event_sender.push_custom_event(ThemeWatchErrorEvent(StatusMessage::error(&message)));
+            }
+        });
+
+        Ok(ThemeWatcher { inotify, watch_descriptor: None })
+    }
+
+    // Only returns on errors
+    fn watch_events(inotify: Inotify, event_sender: &EventSender) -> Result<(), Error> {
+        loop {
+            let events = inotify.read_events()?;
+
+            // Image editors tend to save files by writing a temporary file first and
+            // renaming it afterwards, so the directory is watched instead of the files.
+            let theme_changed = events.iter().any(|event| {
+                event
+                    .name
+                    .as_ref()
+                    .and_then(|name| name.to_str())
+                    .map(|name| Self::WATCHED_FILES.contains(&name))
+                    .unwrap_or(false)
+            });
+
+            if theme_changed {
+                event_sender.push_custom_event(ThemeChangeEvent).map_err(sdl_error)?;
+            }
+        }
+    }
+
+    pub fn watch(&mut self, manifest_path: &Path) -> Result<(), Error> {
+        if let Some(watch_descriptor) = self.watch_descriptor.take() {
+            // The watch is already gone when the directory itself was removed
+            let _ = self.inotify.rm_watch(watch_descriptor);
+        }
+
+        let theme_directory = manifest_path
+            .parent()
+            .ok_or(Error::GeneralError("failed to retrieve parent directory of theme manifest path"))?;
+
+        self.watch_descriptor = Some(self.inotify.add_watch(
+            theme_directory,
+            AddWatchFlags::IN_CLOSE_WRITE | AddWatchFlags::IN_MOVED_TO | AddWatchFlags::IN_CREATE,
+        )?);
+
+        Ok(())
+    }
+}